@@ -0,0 +1,73 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum};
+use serde_json::json;
+
+use crate::{is_stdio, print_json, read_input, write_output, Format, Global};
+
+#[derive(Copy, Clone, Debug, ValueEnum, Eq, PartialEq)]
+pub enum Direction {
+    MidiToTsq,
+    TsqToMidi,
+}
+
+#[derive(Args, Debug)]
+pub struct ConvertArgs {
+    /// Path to the input file, or `-` for stdin
+    #[arg(required = true, value_hint = clap::ValueHint::FilePath)]
+    pub input: Option<PathBuf>,
+    /// Destination, or `-` for stdout (defaults to changing the input's
+    /// extension to .tsq or .mid, or to stdout when reading stdin)
+    #[arg(short, long, value_hint = clap::ValueHint::FilePath)]
+    pub output: Option<PathBuf>,
+    /// Conversion direction
+    #[arg(short, long, value_enum, default_value_t = Direction::MidiToTsq)]
+    pub direction: Direction,
+}
+
+pub fn run(args: &ConvertArgs, global: &Global) -> Result<()> {
+    let input = args
+        .input
+        .as_deref()
+        .expect("clap requires an input for conversion");
+    let (from, to, extension) = match args.direction {
+        Direction::MidiToTsq => ("MIDI", "TSQ", "tsq"),
+        Direction::TsqToMidi => ("TSQ", "MIDI", "mid"),
+    };
+    let output_path = match &args.output {
+        Some(output) => output.clone(),
+        None if is_stdio(input) => PathBuf::from("-"),
+        None => input.with_extension(extension),
+    };
+
+    let data = read_input(input)
+        .with_context(|| format!("failed to read {from} file: {}", input.display()))?;
+    global.detail(format_args!(
+        "Read {} bytes from {}",
+        data.len(),
+        input.display()
+    ));
+    let converted = match args.direction {
+        Direction::MidiToTsq => tsq1::convert_midi_to_tsq_vec(&data),
+        Direction::TsqToMidi => tsq1::convert_tsq_to_midi_vec(&data),
+    }
+    .with_context(|| format!("failed to convert {from} to {to}: {}", input.display()))?;
+    write_output(&output_path, &converted)
+        .with_context(|| format!("failed to write {to} file: {}", output_path.display()))?;
+
+    // Status output would corrupt converted data written to stdout.
+    if global.quiet || is_stdio(&output_path) {
+        global.detail(format_args!("Wrote {} bytes", converted.len()));
+        return Ok(());
+    }
+    match global.format {
+        Format::Human => println!("Wrote {}", output_path.display()),
+        Format::Json => print_json(&json!({
+            "input": input.display().to_string(),
+            "output": output_path.display().to_string(),
+            "bytes": converted.len(),
+        })),
+    }
+    Ok(())
+}
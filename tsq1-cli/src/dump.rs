@@ -0,0 +1,132 @@
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Args;
+use serde_json::{json, Value};
+use tsq1::{EventKindRef, EventRef, EventTime};
+
+use crate::{print_json, read_input, Format, Global};
+
+#[derive(Args, Debug)]
+pub struct DumpArgs {
+    /// TSQ1 file to list, or `-` for stdin
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    input: PathBuf,
+    /// Only list the events of this track
+    #[arg(short, long, value_name = "INDEX")]
+    track: Option<usize>,
+}
+
+/// Payload bytes shown per event unless `--verbose` asks for all of them.
+const PREVIEW_BYTES: usize = 16;
+
+pub fn run(args: &DumpArgs, global: &Global) -> Result<()> {
+    let input = &args.input;
+    let data = read_input(input)
+        .with_context(|| format!("failed to read TSQ file: {}", input.display()))?;
+    let (header, chunks) = tsq1::iter::chunks(&data)
+        .with_context(|| format!("failed to parse TSQ file: {}", input.display()))?;
+
+    let mut events = Vec::new();
+    for chunk in chunks {
+        let chunk =
+            chunk.with_context(|| format!("failed to parse TSQ file: {}", input.display()))?;
+        let Some(track) = chunk.track else { continue };
+        if args.track.is_some_and(|only| only != track) {
+            continue;
+        }
+        for (index, event) in chunk.events(header.flags).enumerate() {
+            let event =
+                event.with_context(|| format!("failed to parse TSQ file: {}", input.display()))?;
+            match global.format {
+                Format::Human => println!("{}", describe(track, index, &event, global.verbose)),
+                Format::Json => events.push(event_json(track, index, &event)),
+            }
+        }
+    }
+    if global.format == Format::Json {
+        print_json(&Value::Array(events));
+    }
+    Ok(())
+}
+
+/// One line per event: track and event index, position, kind and payload.
+/// With `verbose` the event's byte offset and its whole payload are shown.
+fn describe(track: usize, index: usize, event: &EventRef<'_>, verbose: u8) -> String {
+    let mut line = format!("{track}:{index}");
+    if verbose > 0 {
+        let _ = write!(line, " @{:#x}", event.offset);
+    }
+    let _ = match event.time {
+        EventTime::Musical(tick) => write!(line, " tick {tick}"),
+        EventTime::Absolute(abs) => write!(line, " abs {abs}"),
+    };
+    let (kind, data) = match event.kind {
+        EventKindRef::Midi(midi) => {
+            let _ = write!(
+                line,
+                " MIDI {:02X} {:02X} {:02X}",
+                midi.status, midi.data1, midi.data2
+            );
+            return line;
+        }
+        EventKindRef::Meta { meta_type, data } => (format!("META {meta_type:02X}"), data),
+        EventKindRef::SysEx(data) => ("SYSEX".to_string(), data),
+        EventKindRef::Osc { format, data } => (format!("OSC {format:?}"), data),
+        EventKindRef::Custom { type_id, data } => (format!("CUSTOM {type_id:02X}"), data),
+    };
+    let shown = match verbose {
+        0 => &data[..data.len().min(PREVIEW_BYTES)],
+        _ => data,
+    };
+    let _ = write!(line, " {kind} [{}]", hex(shown));
+    if shown.len() < data.len() {
+        let _ = write!(line, " … {} bytes", data.len());
+    }
+    line
+}
+
+fn event_json(track: usize, index: usize, event: &EventRef<'_>) -> Value {
+    let mut value = json!({
+        "track": track,
+        "event": index,
+        "offset": event.offset,
+        "domain": format!("{:?}", event.domain),
+        "delta": event.delta,
+        "position": event.time.value(),
+    });
+    let fields = match event.kind {
+        EventKindRef::Midi(midi) => json!({
+            "kind": "midi",
+            "status": midi.status,
+            "data1": midi.data1,
+            "data2": midi.data2,
+        }),
+        EventKindRef::Meta { meta_type, data } => {
+            json!({ "kind": "meta", "meta_type": meta_type, "data": hex(data) })
+        }
+        EventKindRef::SysEx(data) => json!({ "kind": "sysex", "data": hex(data) }),
+        EventKindRef::Osc { format, data } => {
+            json!({ "kind": "osc", "format": format!("{format:?}"), "data": hex(data) })
+        }
+        EventKindRef::Custom { type_id, data } => {
+            json!({ "kind": "custom", "type_id": type_id, "data": hex(data) })
+        }
+    };
+    if let (Value::Object(event), Value::Object(fields)) = (&mut value, fields) {
+        event.extend(fields);
+    }
+    value
+}
+
+fn hex(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len() * 3);
+    for (i, byte) in data.iter().enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        let _ = write!(out, "{byte:02X}");
+    }
+    out
+}
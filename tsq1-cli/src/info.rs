@@ -0,0 +1,130 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Args;
+use serde_json::{json, Value};
+use tsq1::{Chunk, EventTime, Sequence, Track};
+
+use crate::{print_json, read_input, Format, Global};
+
+#[derive(Args, Debug)]
+pub struct InfoArgs {
+    /// TSQ1 file to inspect, or `-` for stdin
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    input: PathBuf,
+}
+
+/// Names of the header flags this tool knows, by bit.
+const FLAG_NAMES: [(u16, &str); 3] = [
+    (
+        tsq1::FLAG_SYSEX_STATUS_IN_PAYLOAD,
+        "sysex-status-in-payload",
+    ),
+    (tsq1::FLAG_MIDI_THREE_BYTES, "midi-three-bytes"),
+    (tsq1::FLAG_SEQUENTIAL_TRACKS, "sequential-tracks"),
+];
+
+pub fn run(args: &InfoArgs, global: &Global) -> Result<()> {
+    let input = &args.input;
+    let data = read_input(input)
+        .with_context(|| format!("failed to read TSQ file: {}", input.display()))?;
+    let seq = tsq1::read(&data)
+        .with_context(|| format!("failed to parse TSQ file: {}", input.display()))?;
+    global.detail(format_args!(
+        "Read {} bytes from {}",
+        data.len(),
+        input.display()
+    ));
+
+    let header = &seq.header;
+    let flags: Vec<_> = FLAG_NAMES
+        .iter()
+        .filter(|(bit, _)| header.flags & bit != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    let tracks: Vec<_> = seq.tracks.iter().map(TrackSummary::of).collect();
+    match global.format {
+        Format::Human => {
+            println!("version:     {}", header.version);
+            println!("ppq:         {}", header.ppq);
+            println!("abs unit:    {:?}", header.abs_unit);
+            let names: String = flags.iter().map(|name| format!(" {name}")).collect();
+            println!("flags:       {:#06x}{names}", header.flags);
+            println!("tracks:      {}", seq.tracks.len());
+            for (index, track) in tracks.iter().enumerate() {
+                println!(
+                    "  track {index}: {} events, last tick {}, last abs {}",
+                    track.events, track.last_tick, track.last_abs
+                );
+            }
+            println!("chunks:      {}", seq.chunks.len());
+            for chunk in &seq.chunks {
+                let (id, items, what) = describe_chunk(chunk);
+                println!("  {id}: {items} {what}");
+            }
+        }
+        Format::Json => print_json(&json!({
+            "version": header.version,
+            "ppq": header.ppq,
+            "abs_unit": format!("{:?}", header.abs_unit),
+            "flags": header.flags,
+            "flag_names": flags,
+            "tracks": tracks.iter().map(|track| json!({
+                "events": track.events,
+                "last_tick": track.last_tick,
+                "last_abs": track.last_abs,
+            })).collect::<Vec<_>>(),
+            "chunks": chunks_json(&seq),
+        })),
+    }
+    Ok(())
+}
+
+struct TrackSummary {
+    events: usize,
+    /// Position of the last event on each clock.
+    last_tick: u64,
+    last_abs: u64,
+}
+
+impl TrackSummary {
+    fn of(track: &Track) -> Self {
+        let mut summary = TrackSummary {
+            events: track.events.len(),
+            last_tick: 0,
+            last_abs: 0,
+        };
+        for (time, _) in track.timed_events() {
+            match time {
+                EventTime::Musical(tick) => summary.last_tick = tick,
+                EventTime::Absolute(abs) => summary.last_abs = abs,
+            }
+        }
+        summary
+    }
+}
+
+/// Chunk ID, item count and what the items are.
+fn describe_chunk(chunk: &Chunk) -> (String, usize, &'static str) {
+    let (items, what) = match chunk {
+        Chunk::TempoMap(map) => (map.entries.len(), "tempo entries"),
+        Chunk::Sync(map) => (map.anchors.len(), "sync anchors"),
+        Chunk::Markers(locators) => (locators.len(), "locators"),
+        Chunk::Unknown(raw) => (raw.data.len(), "bytes"),
+    };
+    (
+        String::from_utf8_lossy(&chunk.id()).into_owned(),
+        items,
+        what,
+    )
+}
+
+fn chunks_json(seq: &Sequence) -> Value {
+    seq.chunks
+        .iter()
+        .map(|chunk| {
+            let (id, items, what) = describe_chunk(chunk);
+            json!({ "id": id, "count": items, "unit": what })
+        })
+        .collect()
+}
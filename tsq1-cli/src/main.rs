@@ -1,57 +1,145 @@
-use std::path::PathBuf;
+use std::fmt::Display;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::ExitCode;
 
-use anyhow::{Context, Result};
-use clap::{Parser, ValueEnum};
+use anyhow::Result;
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use serde_json::Value;
 
-#[derive(Copy, Clone, Debug, ValueEnum, Eq, PartialEq)]
-enum Direction {
-    MidiToTsq,
-    TsqToMidi,
-}
+mod convert;
+mod dump;
+mod edit;
+mod info;
+mod validate;
 
-/// Convert between Standard MIDI Files and TSQ1 sequences.
+/// Convert, inspect and check TSQ1 sequences.
+///
+/// Without a subcommand the arguments are those of `convert`, so
+/// `tsq1-cli song.mid` keeps working as before.
 #[derive(Parser, Debug)]
 #[command(author, version, about = "TSQ1 toolkit", long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
-    /// Path to the input SMF (.mid) file
-    #[arg(value_hint = clap::ValueHint::FilePath)]
-    input: PathBuf,
-    /// Destination for the generated TSQ file (defaults to changing extension to .tsq)
-    #[arg(short, long, value_hint = clap::ValueHint::FilePath)]
-    output: Option<PathBuf>,
-    /// Conversion direction
-    #[arg(short, long, value_enum, default_value_t = Direction::MidiToTsq)]
-    direction: Direction,
-}
-
-fn main() -> Result<()> {
-    let cli = Cli::parse();
-    let output_path = cli.output.clone().unwrap_or_else(|| match cli.direction {
-        Direction::MidiToTsq => cli.input.with_extension("tsq"),
-        Direction::TsqToMidi => cli.input.with_extension("mid"),
-    });
-
-    match cli.direction {
-        Direction::MidiToTsq => {
-            let midi_data = std::fs::read(&cli.input)
-                .with_context(|| format!("failed to read MIDI file: {}", cli.input.display()))?;
-            let tsq_data = tsq1::convert_midi_to_tsq_vec(&midi_data).with_context(|| {
-                format!("failed to convert MIDI to TSQ: {}", cli.input.display())
-            })?;
-            std::fs::write(&output_path, tsq_data)
-                .with_context(|| format!("failed to write TSQ file: {}", output_path.display()))?;
-        }
-        Direction::TsqToMidi => {
-            let tsq_data = std::fs::read(&cli.input)
-                .with_context(|| format!("failed to read TSQ file: {}", cli.input.display()))?;
-            let midi_data = tsq1::convert_tsq_to_midi_vec(&tsq_data).with_context(|| {
-                format!("failed to convert TSQ to MIDI: {}", cli.input.display())
-            })?;
-            std::fs::write(&output_path, midi_data)
-                .with_context(|| format!("failed to write MIDI file: {}", output_path.display()))?;
+    #[command(flatten)]
+    global: Global,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    convert: convert::ConvertArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Convert between Standard MIDI Files and TSQ1
+    Convert(convert::ConvertArgs),
+    /// Summarize the header, tracks and chunks of a TSQ1 file
+    Info(info::InfoArgs),
+    /// List the events of a TSQ1 file
+    Dump(dump::DumpArgs),
+    /// Check TSQ1 files against the specification
+    Validate(validate::ValidateArgs),
+    /// Rewrite a TSQ1 file with tracks, chunks or layout changed
+    Edit(edit::EditArgs),
+}
+
+/// Options shared by every subcommand.
+#[derive(Args, Debug)]
+struct Global {
+    /// Print more detail; repeat for even more
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+    /// Print nothing but errors and requested data
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Format of reports and status output
+    #[arg(long, global = true, value_enum, default_value_t = Format::Human)]
+    format: Format,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum, Eq, PartialEq)]
+enum Format {
+    Human,
+    Json,
+}
+
+impl Global {
+    /// Print a progress note to stderr with `--verbose`.
+    fn detail(&self, message: impl Display) {
+        if self.verbose > 0 {
+            eprintln!("{message}");
         }
     }
+}
+
+fn main() -> Result<ExitCode> {
+    let cli = Cli::parse();
+    let global = &cli.global;
+    match &cli.command {
+        Some(Command::Convert(args)) => convert::run(args, global)?,
+        Some(Command::Info(args)) => info::run(args, global)?,
+        Some(Command::Dump(args)) => dump::run(args, global)?,
+        Some(Command::Validate(args)) => return validate::run(args, global),
+        Some(Command::Edit(args)) => edit::run(args, global)?,
+        None => convert::run(&cli.convert, global)?,
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Whether `path` is `-`, standing for stdin or stdout.
+fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Read a whole file, or stdin for `-`.
+fn read_input(path: &Path) -> io::Result<Vec<u8>> {
+    if is_stdio(path) {
+        let mut data = Vec::new();
+        io::stdin().lock().read_to_end(&mut data)?;
+        Ok(data)
+    } else {
+        std::fs::read(path)
+    }
+}
+
+/// Write a whole file, or stdout for `-`.
+fn write_output(path: &Path, data: &[u8]) -> io::Result<()> {
+    if is_stdio(path) {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(data)?;
+        stdout.flush()
+    } else {
+        std::fs::write(path, data)
+    }
+}
 
-    println!("Wrote {}", output_path.display());
-    Ok(())
+fn print_json(value: &Value) {
+    println!("{value:#}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn command_is_well_formed() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn plain_invocation_still_converts() {
+        let cli = Cli::try_parse_from(["tsq1-cli", "song.tsq", "-d", "tsq-to-midi", "-v"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.convert.input.as_deref(), Some(Path::new("song.tsq")));
+        assert_eq!(cli.convert.direction, convert::Direction::TsqToMidi);
+        assert_eq!(cli.global.verbose, 1);
+
+        let cli =
+            Cli::try_parse_from(["tsq1-cli", "validate", "a.tsq", "--format", "json"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Validate(_))));
+        assert_eq!(cli.global.format, Format::Json);
+
+        assert!(Cli::try_parse_from(["tsq1-cli"]).is_err());
+    }
 }
@@ -0,0 +1,101 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use anyhow::Result;
+use clap::Args;
+use serde_json::{json, Value};
+
+use crate::{print_json, read_input, Format, Global};
+
+#[derive(Args, Debug)]
+pub struct ValidateArgs {
+    /// TSQ1 files to check, or `-` for stdin
+    #[arg(required = true, value_hint = clap::ValueHint::FilePath)]
+    files: Vec<PathBuf>,
+    /// Fail on warnings as well as errors
+    #[arg(long)]
+    deny_warnings: bool,
+}
+
+/// Validate every file and print the findings; fails if any file has errors
+/// (or warnings, with `--deny-warnings`) or cannot be read. `--quiet` leaves
+/// out warnings and the summary.
+pub fn run(args: &ValidateArgs, global: &Global) -> Result<ExitCode> {
+    let mut failed = false;
+    let mut files = Vec::new();
+    let (mut errors, mut warnings) = (0, 0);
+    for path in &args.files {
+        let report = read_input(path).map(|data| tsq1::validate(&data));
+        failed |= match &report {
+            Ok(report) => !report.is_valid() || (args.deny_warnings && !report.warnings.is_empty()),
+            Err(_) => true,
+        };
+        match global.format {
+            Format::Human => match &report {
+                Ok(report) => {
+                    let shown = if global.quiet {
+                        &[][..]
+                    } else {
+                        &report.warnings[..]
+                    };
+                    for (severity, found) in [("error", &report.errors[..]), ("warning", shown)] {
+                        for diagnostic in found {
+                            println!("{}: {severity}: {diagnostic}", path.display());
+                        }
+                    }
+                    errors += report.errors.len();
+                    warnings += report.warnings.len();
+                }
+                Err(err) => {
+                    println!("{}: error: failed to read file: {err}", path.display());
+                    errors += 1;
+                }
+            },
+            Format::Json => files.push(match &report {
+                Ok(report) => json!({
+                    "file": path.display().to_string(),
+                    "valid": report.is_valid(),
+                    "errors": report.errors.iter().map(diagnostic_json).collect::<Vec<_>>(),
+                    "warnings": report.warnings.iter().map(diagnostic_json).collect::<Vec<_>>(),
+                }),
+                Err(err) => json!({
+                    "file": path.display().to_string(),
+                    "valid": false,
+                    "read_error": err.to_string(),
+                }),
+            }),
+        }
+    }
+
+    match global.format {
+        Format::Human if !global.quiet => println!(
+            "{} file(s) checked: {errors} error(s), {warnings} warning(s)",
+            args.files.len()
+        ),
+        Format::Human => {}
+        Format::Json => print_json(&Value::Array(files)),
+    }
+    Ok(if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+fn diagnostic_json(diagnostic: &tsq1::DecodeError) -> Value {
+    let message = match diagnostic.error.cause() {
+        tsq1::Error::Invalid(message)
+        | tsq1::Error::Unsupported(message)
+        | tsq1::Error::DataOverflow(message) => message.to_string(),
+        other => other.to_string(),
+    };
+    json!({
+        "kind": format!("{:?}", diagnostic.kind()),
+        "message": message,
+        "offset": diagnostic.offset,
+        "chunk_id": diagnostic.chunk_id.map(|id| String::from_utf8_lossy(&id).into_owned()),
+        "chunk_index": diagnostic.chunk_index,
+        "track": diagnostic.track,
+        "event": diagnostic.event,
+    })
+}
@@ -0,0 +1,232 @@
+//! Typed codecs for custom / vendor events (`EventKind = 0x7E`, spec §4.5).
+//!
+//! Custom payloads are opaque bytes to this crate. Applications that know the
+//! layout of a `TypeID` register a [`CustomCodec`] for it in a
+//! [`CustomRegistry`]; [`crate::read_with_registry`] then attaches the decoded
+//! value to each matching [`CustomEvent`] and
+//! [`Sequence::to_vec_with_registry`](crate::Sequence::to_vec_with_registry)
+//! encodes it again. Types without a codec pass through unchanged.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::fmt::Debug;
+
+use crate::event::CustomEvent;
+use crate::Error;
+
+/// A decoded custom payload.
+///
+/// Implemented for every `Clone + PartialEq + Debug` type, so codecs can
+/// return their own payload types boxed.
+pub trait CustomValue: Any + Debug + Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+    fn clone_box(&self) -> Box<dyn CustomValue>;
+    fn eq_value(&self, other: &dyn CustomValue) -> bool;
+}
+
+impl<T: Any + Debug + Clone + PartialEq + Send + Sync> CustomValue for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn clone_box(&self) -> Box<dyn CustomValue> {
+        Box::new(self.clone())
+    }
+
+    fn eq_value(&self, other: &dyn CustomValue) -> bool {
+        other.as_any().downcast_ref::<T>() == Some(self)
+    }
+}
+
+impl Clone for Box<dyn CustomValue> {
+    fn clone(&self) -> Self {
+        (**self).clone_box()
+    }
+}
+
+impl PartialEq for dyn CustomValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.eq_value(other)
+    }
+}
+
+impl Eq for dyn CustomValue {}
+
+/// Encoder and decoder for the payload of one custom `TypeID`.
+pub trait CustomCodec: Send + Sync {
+    /// Decode a stored payload.
+    fn decode(&self, data: &[u8]) -> Result<Box<dyn CustomValue>, Error>;
+
+    /// Append the encoded form of `value` to `out`.
+    fn encode(&self, value: &dyn CustomValue, out: &mut Vec<u8>) -> Result<(), Error>;
+}
+
+/// Codecs for custom event types, keyed by `TypeID`.
+#[derive(Default)]
+pub struct CustomRegistry {
+    codecs: Vec<(u8, Box<dyn CustomCodec>)>,
+}
+
+impl CustomRegistry {
+    pub fn new() -> Self {
+        CustomRegistry::default()
+    }
+
+    /// Register `codec` for `type_id`, replacing any previous codec.
+    pub fn register(&mut self, type_id: u8, codec: impl CustomCodec + 'static) {
+        let codec: Box<dyn CustomCodec> = Box::new(codec);
+        match self.codecs.iter_mut().find(|(id, _)| *id == type_id) {
+            Some(entry) => entry.1 = codec,
+            None => self.codecs.push((type_id, codec)),
+        }
+    }
+
+    /// The codec registered for `type_id`, if any.
+    pub fn get(&self, type_id: u8) -> Option<&dyn CustomCodec> {
+        self.codecs
+            .iter()
+            .find(|(id, _)| *id == type_id)
+            .map(|(_, codec)| codec.as_ref())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.codecs.is_empty()
+    }
+
+    /// Attach the decoded value to `event` if its type has a codec.
+    pub fn decode(&self, event: &mut CustomEvent) -> Result<(), Error> {
+        if let Some(codec) = self.get(event.type_id) {
+            event.value = Some(codec.decode(&event.data)?);
+        }
+        Ok(())
+    }
+
+    /// Append the payload of `event` to `out`.
+    ///
+    /// A typed value is encoded with the registered codec and fails if there
+    /// is none; otherwise `data` is written verbatim.
+    pub fn encode(&self, event: &CustomEvent, out: &mut Vec<u8>) -> Result<(), Error> {
+        match &event.value {
+            Some(value) => self
+                .get(event.type_id)
+                .ok_or(Error::Unsupported(
+                    "no codec registered for custom event type",
+                ))?
+                .encode(value.as_ref(), out),
+            None => {
+                out.extend_from_slice(&event.data);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Debug for CustomRegistry {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_set()
+            .entries(self.codecs.iter().map(|(id, _)| id))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{Event, EventKind};
+    use crate::sequence::{Sequence, Track};
+    use alloc::vec;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Marker {
+        id: u16,
+    }
+
+    struct MarkerCodec;
+
+    impl CustomCodec for MarkerCodec {
+        fn decode(&self, data: &[u8]) -> Result<Box<dyn CustomValue>, Error> {
+            match data {
+                [lo, hi] => Ok(Box::new(Marker {
+                    id: u16::from_le_bytes([*lo, *hi]),
+                })),
+                _ => Err(Error::Invalid("marker payload must be two bytes")),
+            }
+        }
+
+        fn encode(&self, value: &dyn CustomValue, out: &mut Vec<u8>) -> Result<(), Error> {
+            let marker = value
+                .as_any()
+                .downcast_ref::<Marker>()
+                .ok_or(Error::Invalid("not a marker"))?;
+            out.extend_from_slice(&marker.id.to_le_bytes());
+            Ok(())
+        }
+    }
+
+    fn registry() -> CustomRegistry {
+        let mut registry = CustomRegistry::new();
+        registry.register(0x10, MarkerCodec);
+        registry
+    }
+
+    fn sequence(events: Vec<Event>) -> Sequence {
+        let mut seq = Sequence::new(480);
+        seq.tracks.push(Track::with_events(events));
+        seq
+    }
+
+    #[test]
+    fn registered_types_are_decoded_and_encoded() {
+        let seq = sequence(vec![
+            Event::musical(
+                0,
+                EventKind::Custom(CustomEvent::new(0x10, vec![0x34, 0x12])),
+            ),
+            Event::musical(5, EventKind::Custom(CustomEvent::new(0x11, vec![1, 2, 3]))),
+        ]);
+        let bytes = seq.to_vec().unwrap();
+
+        let registry = registry();
+        let decoded = crate::read_with_registry(&bytes, &registry).unwrap();
+        let events = &decoded.tracks[0].events;
+        let EventKind::Custom(known) = &events[0].kind else {
+            panic!("expected custom event");
+        };
+        assert_eq!(known.value_as::<Marker>(), Some(&Marker { id: 0x1234 }));
+        let EventKind::Custom(unknown) = &events[1].kind else {
+            panic!("expected custom event");
+        };
+        assert_eq!(unknown.value, None);
+        assert_eq!(unknown.data, [1, 2, 3]);
+
+        assert_eq!(decoded.to_vec_with_registry(&registry).unwrap(), bytes);
+
+        let bad = sequence(vec![Event::musical(
+            0,
+            EventKind::Custom(CustomEvent::new(0x10, vec![1])),
+        )]);
+        let bad = bad.to_vec().unwrap();
+        assert!(crate::read(&bad).is_ok());
+        assert!(matches!(
+            crate::read_with_registry(&bad, &registry),
+            Err(err) if err.kind() == crate::ErrorKind::Invalid
+        ));
+    }
+
+    #[test]
+    fn typed_values_need_a_codec_on_write() {
+        let seq = sequence(vec![Event::musical(
+            0,
+            EventKind::Custom(CustomEvent::typed(0x10, Marker { id: 7 })),
+        )]);
+        assert!(matches!(seq.to_vec(), Err(Error::Unsupported(_))));
+
+        let bytes = seq.to_vec_with_registry(&registry()).unwrap();
+        let plain = crate::read(&bytes).unwrap();
+        assert_eq!(
+            plain.tracks[0].events[0].kind,
+            EventKind::Custom(CustomEvent::new(0x10, vec![7, 0]))
+        );
+    }
+}
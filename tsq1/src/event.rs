@@ -0,0 +1,267 @@
+//! Owned representation of TRK chunk events (spec §3 and §4).
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::custom::CustomValue;
+
+/// EventKind value for OSC events.
+pub const EK_OSC: u8 = 0x00;
+/// EventKind value for MIDI channel messages.
+pub const EK_MIDI: u8 = 0x01;
+/// EventKind value for SMF-style meta events.
+pub const EK_META: u8 = 0x02;
+/// EventKind value for System Exclusive payloads.
+pub const EK_SYSEX: u8 = 0x03;
+/// EventKind value for custom / vendor extensions.
+pub const EK_CUSTOM: u8 = 0x7E;
+
+const DOMAIN_BIT: u8 = 0x80;
+
+/// Time axis an event's delta is expressed in (`Header.bit7`).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum Domain {
+    /// Delta is expressed in PPQ ticks.
+    #[default]
+    Musical,
+    /// Delta is expressed in the sequence's `AbsUnit`.
+    Absolute,
+}
+
+/// Cumulative position of an event on its own domain's clock.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum EventTime {
+    /// Ticks since the start of the track.
+    Musical(u64),
+    /// `AbsUnit`s since the start of the track.
+    Absolute(u64),
+}
+
+impl EventTime {
+    pub fn domain(&self) -> Domain {
+        match self {
+            EventTime::Musical(_) => Domain::Musical,
+            EventTime::Absolute(_) => Domain::Absolute,
+        }
+    }
+
+    /// The position on its domain's clock.
+    pub fn value(&self) -> u64 {
+        match self {
+            EventTime::Musical(value) | EventTime::Absolute(value) => *value,
+        }
+    }
+}
+
+/// A single event stored in a `"TRK "` chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// Time axis of `delta`.
+    pub domain: Domain,
+    /// Delta time since the previous event, in ticks or `AbsUnit`.
+    pub delta: u64,
+    /// Event payload.
+    pub kind: EventKind,
+}
+
+impl Event {
+    /// Create a musical-domain event.
+    pub fn musical(delta: u64, kind: EventKind) -> Self {
+        Event {
+            domain: Domain::Musical,
+            delta,
+            kind,
+        }
+    }
+
+    /// Create an absolute-domain event.
+    pub fn absolute(delta: u64, kind: EventKind) -> Self {
+        Event {
+            domain: Domain::Absolute,
+            delta,
+            kind,
+        }
+    }
+
+    /// The encoded event header byte (domain bit plus EventKind).
+    pub fn header_byte(&self) -> u8 {
+        let domain = match self.domain {
+            Domain::Musical => 0,
+            Domain::Absolute => DOMAIN_BIT,
+        };
+        domain | self.kind.code()
+    }
+}
+
+/// Payload of an [`Event`], one variant per spec §3.2 EventKind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventKind {
+    /// OSC message or bundle (`EK_OSC`).
+    Osc(OscEvent),
+    /// MIDI channel message (`EK_MIDI`).
+    Midi(MidiEvent),
+    /// SMF-style meta event (`EK_META`).
+    Meta(MetaEvent),
+    /// System Exclusive payload exactly as stored (`EK_SYSEX`).
+    SysEx(Vec<u8>),
+    /// Vendor-specific extension (`EK_CUSTOM`).
+    Custom(CustomEvent),
+}
+
+impl EventKind {
+    /// The EventKind value stored in the low seven header bits.
+    pub fn code(&self) -> u8 {
+        match self {
+            EventKind::Osc(_) => EK_OSC,
+            EventKind::Midi(_) => EK_MIDI,
+            EventKind::Meta(_) => EK_META,
+            EventKind::SysEx(_) => EK_SYSEX,
+            EventKind::Custom(_) => EK_CUSTOM,
+        }
+    }
+}
+
+/// Encoding of an OSC payload (`OscFormat`).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum OscFormat {
+    /// Byte-accurate OSC 1.0/1.1 datagram.
+    Raw,
+    /// MessagePack encoded schema.
+    MsgPack,
+    /// CBOR encoded schema.
+    Cbor,
+    /// Any other (reserved) format value, kept verbatim.
+    Other(u8),
+}
+
+impl OscFormat {
+    /// Interpret an `OscFormat` byte.
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            0x00 => OscFormat::Raw,
+            0x01 => OscFormat::MsgPack,
+            0x02 => OscFormat::Cbor,
+            other => OscFormat::Other(other),
+        }
+    }
+
+    /// The encoded `OscFormat` byte.
+    pub fn as_u8(self) -> u8 {
+        match self {
+            OscFormat::Raw => 0x00,
+            OscFormat::MsgPack => 0x01,
+            OscFormat::Cbor => 0x02,
+            OscFormat::Other(other) => other,
+        }
+    }
+}
+
+/// OSC event payload (`[OscFormat][Length:VLQ][Data]`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OscEvent {
+    pub format: OscFormat,
+    pub data: Vec<u8>,
+}
+
+/// MIDI channel message (`[Status][Data1][Data2]`).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct MidiEvent {
+    pub status: u8,
+    pub data1: u8,
+    pub data2: u8,
+}
+
+impl MidiEvent {
+    pub fn new(status: u8, data1: u8, data2: u8) -> Self {
+        MidiEvent {
+            status,
+            data1,
+            data2,
+        }
+    }
+
+    /// MIDI channel (low nibble of the status byte).
+    pub fn channel(&self) -> u8 {
+        self.status & 0x0F
+    }
+
+    /// Whether the message carries a single data byte (program change and
+    /// channel aftertouch). In the three-byte layout `data2` is then padding.
+    pub fn is_single_data_byte(&self) -> bool {
+        matches!(self.status >> 4, 0xC | 0xD)
+    }
+}
+
+/// Meta event (`[MetaType][Length:VLQ][Data]`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetaEvent {
+    pub meta_type: u8,
+    pub data: Vec<u8>,
+}
+
+impl MetaEvent {
+    pub fn new(meta_type: u8, data: Vec<u8>) -> Self {
+        MetaEvent { meta_type, data }
+    }
+}
+
+/// Custom / vendor event (`[TypeID][Length:VLQ][Data]`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomEvent {
+    pub type_id: u8,
+    /// Payload exactly as stored.
+    pub data: Vec<u8>,
+    /// Value decoded by a registered [`CustomCodec`](crate::custom::CustomCodec).
+    /// When set, it is encoded in place of `data` on write.
+    pub value: Option<Box<dyn CustomValue>>,
+}
+
+impl CustomEvent {
+    /// Opaque custom event.
+    pub fn new(type_id: u8, data: Vec<u8>) -> Self {
+        CustomEvent {
+            type_id,
+            data,
+            value: None,
+        }
+    }
+
+    /// Custom event carrying a typed value, encoded by the codec registered
+    /// for `type_id` on write.
+    pub fn typed(type_id: u8, value: impl CustomValue) -> Self {
+        CustomEvent {
+            type_id,
+            data: Vec::new(),
+            value: Some(Box::new(value)),
+        }
+    }
+
+    /// The decoded value, if it is a `T`.
+    pub fn value_as<T: CustomValue>(&self) -> Option<&T> {
+        let value: &dyn CustomValue = self.value.as_deref()?;
+        value.as_any().downcast_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn header_byte_combines_domain_and_kind() {
+        let midi = Event::absolute(0, EventKind::Midi(MidiEvent::new(0x90, 60, 100)));
+        assert_eq!(midi.header_byte(), 0x81);
+
+        let custom = Event::musical(0, EventKind::Custom(CustomEvent::new(1, vec![])));
+        assert_eq!(custom.header_byte(), 0x7E);
+    }
+
+    #[test]
+    fn osc_format_preserves_reserved_values() {
+        for value in [0x00, 0x01, 0x02, 0x20, 0x7F] {
+            assert_eq!(OscFormat::from_u8(value).as_u8(), value);
+        }
+        assert_eq!(OscFormat::from_u8(0x21), OscFormat::Other(0x21));
+    }
+}
@@ -0,0 +1,577 @@
+//! Zero-copy iteration over TSQ1 data.
+//!
+//! [`chunks`] walks the chunk container of a complete file and [`EventIter`]
+//! decodes the events of one `"TRK "` chunk on demand, borrowing payloads
+//! from the input. Neither allocates, so both suit `no_std` targets and
+//! captures too large to decode into a [`Sequence`](crate::Sequence).
+//!
+//! Errors carry the byte offset of the field that could not be decoded and,
+//! when known, the chunk, track and event it belongs to.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::event::{
+    CustomEvent, Domain, Event, EventKind, EventTime, MetaEvent, MidiEvent, OscEvent, OscFormat,
+    EK_CUSTOM, EK_META, EK_MIDI, EK_OSC, EK_SYSEX,
+};
+use crate::sequence::{
+    domain_index, AbsUnit, Header, FLAG_MIDI_THREE_BYTES, HEADER_SIZE, MAGIC, VERSION,
+};
+use crate::{read_u8, read_vlq, take_slice, Error, ErrorKind};
+
+/// A decoding error at a byte offset, with the chunk, track and event it
+/// occurred in when known.
+#[derive(Debug)]
+pub struct DecodeError {
+    /// Offset of the first byte of the field that failed to decode.
+    pub offset: usize,
+    pub chunk_id: Option<[u8; 4]>,
+    /// Index of the chunk among all chunks of the file.
+    pub chunk_index: Option<usize>,
+    /// Index of the `"TRK "` chunk among the file's tracks.
+    pub track: Option<usize>,
+    /// Index of the event within its track.
+    pub event: Option<usize>,
+    pub error: Error,
+}
+
+impl DecodeError {
+    pub fn new(offset: usize, error: Error) -> Self {
+        DecodeError {
+            offset,
+            chunk_id: None,
+            chunk_index: None,
+            track: None,
+            event: None,
+            error,
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.error.kind()
+    }
+
+    /// Attribute the error to chunk number `index` with ID `id`, which is
+    /// track number `track` if it is a `"TRK "` chunk.
+    pub(crate) fn in_chunk(mut self, id: [u8; 4], index: usize, track: Option<usize>) -> Self {
+        self.chunk_id = Some(id);
+        self.chunk_index = Some(index);
+        self.track = track;
+        self
+    }
+
+    pub(crate) fn at_event(mut self, index: usize) -> Self {
+        self.event = Some(index);
+        self
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.error, self.offset)?;
+        if let Some(index) = self.chunk_index {
+            write!(f, " in chunk {index}")?;
+        }
+        if let Some(id) = self.chunk_id {
+            f.write_str(" \"")?;
+            for byte in id {
+                match byte {
+                    0x20..=0x7E => write!(f, "{}", byte as char)?,
+                    _ => write!(f, "\\x{byte:02X}")?,
+                }
+            }
+            f.write_str("\"")?;
+        }
+        if let Some(track) = self.track {
+            write!(f, ", track {track}")?;
+        }
+        if let Some(event) = self.event {
+            write!(f, ", event {event}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeError {}
+
+impl From<DecodeError> for Error {
+    fn from(err: DecodeError) -> Self {
+        Error::Located(Box::new(err))
+    }
+}
+
+/// Payload of an [`EventRef`], borrowed from the track data.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EventKindRef<'a> {
+    /// OSC payload as stored; it is not validated.
+    Osc {
+        format: OscFormat,
+        data: &'a [u8],
+    },
+    /// MIDI channel message; `data2` is `0` for single-byte messages in the
+    /// legacy layout.
+    Midi(MidiEvent),
+    Meta {
+        meta_type: u8,
+        data: &'a [u8],
+    },
+    SysEx(&'a [u8]),
+    Custom {
+        type_id: u8,
+        data: &'a [u8],
+    },
+}
+
+impl<'a> EventKindRef<'a> {
+    /// The EventKind value stored in the low seven header bits.
+    pub fn code(&self) -> u8 {
+        match self {
+            EventKindRef::Osc { .. } => EK_OSC,
+            EventKindRef::Midi(_) => EK_MIDI,
+            EventKindRef::Meta { .. } => EK_META,
+            EventKindRef::SysEx(_) => EK_SYSEX,
+            EventKindRef::Custom { .. } => EK_CUSTOM,
+        }
+    }
+
+    /// Copy the payload into an owned [`EventKind`].
+    pub fn to_owned_kind(&self) -> EventKind {
+        match *self {
+            EventKindRef::Osc { format, data } => EventKind::Osc(OscEvent {
+                format,
+                data: data.to_vec(),
+            }),
+            EventKindRef::Midi(midi) => EventKind::Midi(midi),
+            EventKindRef::Meta { meta_type, data } => {
+                EventKind::Meta(MetaEvent::new(meta_type, data.to_vec()))
+            }
+            EventKindRef::SysEx(data) => EventKind::SysEx(data.to_vec()),
+            EventKindRef::Custom { type_id, data } => {
+                EventKind::Custom(CustomEvent::new(type_id, data.to_vec()))
+            }
+        }
+    }
+}
+
+/// An event decoded in place by [`EventIter`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct EventRef<'a> {
+    pub domain: Domain,
+    pub delta: u64,
+    /// Cumulative position on the event's own axis.
+    pub time: EventTime,
+    /// Offset of the event header byte.
+    pub offset: usize,
+    pub kind: EventKindRef<'a>,
+}
+
+impl<'a> EventRef<'a> {
+    /// Copy the event into an owned [`Event`].
+    pub fn to_event(&self) -> Event {
+        Event {
+            domain: self.domain,
+            delta: self.delta,
+            kind: self.kind.to_owned_kind(),
+        }
+    }
+}
+
+/// Lazy decoder for the events of a `"TRK "` chunk.
+///
+/// Like [`Track::timed_events`](crate::Track::timed_events), each domain keeps
+/// its own clock and positions saturate at `u64::MAX`. After the first error
+/// the iterator is exhausted.
+#[derive(Debug, Clone)]
+pub struct EventIter<'a> {
+    cursor: Cursor<'a>,
+    three_bytes: bool,
+    clocks: [u64; 2],
+    /// Index of the next event.
+    index: usize,
+    /// ID, index and track number of the chunk, for errors.
+    chunk: Option<([u8; 4], usize, Option<usize>)>,
+    failed: bool,
+}
+
+impl<'a> EventIter<'a> {
+    /// Iterate over the events in `data`, the payload of a `"TRK "` chunk.
+    /// `flags` are the file's header flags, which select the MIDI layout.
+    pub fn new(data: &'a [u8], flags: u16) -> Self {
+        EventIter {
+            cursor: Cursor::new(data, 0),
+            three_bytes: flags & FLAG_MIDI_THREE_BYTES != 0,
+            clocks: [0; 2],
+            index: 0,
+            chunk: None,
+            failed: false,
+        }
+    }
+
+    /// Report offsets relative to a position `base` bytes before `data`,
+    /// such as the start of the file.
+    pub fn with_base_offset(mut self, base: usize) -> Self {
+        self.cursor = Cursor::new(self.cursor.data, base);
+        self
+    }
+
+    /// Offset of the next event.
+    pub fn offset(&self) -> usize {
+        self.cursor.offset()
+    }
+
+    /// Bytes not decoded yet.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.cursor.data
+    }
+
+    fn decode(&mut self) -> Result<EventRef<'a>, DecodeError> {
+        let cursor = &mut self.cursor;
+        let offset = cursor.offset();
+        let header = cursor.field(|data| match data.first().map(|h| h & 0x7F) {
+            Some(EK_OSC | EK_MIDI | EK_META | EK_SYSEX | EK_CUSTOM) | None => read_u8(data),
+            Some(_) => Err(Error::Unsupported("unknown event kind")),
+        })?;
+        let domain = match header & 0x80 {
+            0 => Domain::Musical,
+            _ => Domain::Absolute,
+        };
+        let clock = &mut self.clocks[domain_index(domain)];
+        let delta = cursor.field(read_vlq)?;
+        *clock = clock.saturating_add(delta);
+        let time = match domain {
+            Domain::Musical => EventTime::Musical(*clock),
+            Domain::Absolute => EventTime::Absolute(*clock),
+        };
+
+        let kind = match header & 0x7F {
+            EK_OSC => EventKindRef::Osc {
+                format: OscFormat::from_u8(cursor.field(read_u8)?),
+                data: cursor.payload()?,
+            },
+            EK_MIDI => {
+                let status = cursor.field(|data| match data.first() {
+                    Some(0x80..=0xEF) | None => read_u8(data),
+                    Some(_) => Err(Error::Invalid("invalid MIDI status byte")),
+                })?;
+                let data1 = cursor.field(read_u8)?;
+                let data2 = if !self.three_bytes && matches!(status >> 4, 0xC | 0xD) {
+                    0
+                } else {
+                    cursor.field(read_u8)?
+                };
+                EventKindRef::Midi(MidiEvent::new(status, data1, data2))
+            }
+            EK_META => EventKindRef::Meta {
+                meta_type: cursor.field(read_u8)?,
+                data: cursor.payload()?,
+            },
+            EK_SYSEX => EventKindRef::SysEx(cursor.payload()?),
+            _ => EventKindRef::Custom {
+                type_id: cursor.field(read_u8)?,
+                data: cursor.payload()?,
+            },
+        };
+        Ok(EventRef {
+            domain,
+            delta,
+            time,
+            offset,
+            kind,
+        })
+    }
+}
+
+impl<'a> Iterator for EventIter<'a> {
+    type Item = Result<EventRef<'a>, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.cursor.data.is_empty() {
+            return None;
+        }
+        let result = self.decode().map_err(|err| {
+            let err = err.at_event(self.index);
+            match self.chunk {
+                Some((id, index, track)) => err.in_chunk(id, index, track),
+                None => err,
+            }
+        });
+        self.index += 1;
+        self.failed = result.is_err();
+        Some(result)
+    }
+}
+
+/// A chunk of a TSQ1 file, borrowed from the file data.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ChunkRef<'a> {
+    pub id: [u8; 4],
+    /// Index of the chunk among all chunks of the file.
+    pub index: usize,
+    /// Index among the file's tracks, for `"TRK "` chunks.
+    pub track: Option<usize>,
+    /// Offset of the chunk's ID in the file.
+    pub offset: usize,
+    pub data: &'a [u8],
+}
+
+impl<'a> ChunkRef<'a> {
+    /// Events of a `"TRK "` chunk, with offsets relative to the file.
+    /// Errors name this chunk.
+    pub fn events(&self, flags: u16) -> EventIter<'a> {
+        let mut events = EventIter::new(self.data, flags).with_base_offset(self.offset + 8);
+        events.chunk = Some((self.id, self.index, self.track));
+        events
+    }
+
+    /// `error`, located at `offset` within this chunk.
+    pub fn error_at(&self, offset: usize, error: Error) -> DecodeError {
+        DecodeError::new(offset, error).in_chunk(self.id, self.index, self.track)
+    }
+}
+
+/// Lazy iterator over the chunks of a TSQ1 file, created by [`chunks`].
+/// After the first error the iterator is exhausted.
+#[derive(Debug, Clone)]
+pub struct ChunkIter<'a> {
+    cursor: Cursor<'a>,
+    /// Chunks and tracks yielded so far.
+    index: usize,
+    tracks: usize,
+    failed: bool,
+}
+
+impl<'a> Iterator for ChunkIter<'a> {
+    type Item = Result<ChunkRef<'a>, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.cursor.data.is_empty() {
+            return None;
+        }
+        let cursor = &mut self.cursor;
+        let offset = cursor.offset();
+        let result = cursor
+            .field(|data| {
+                take_slice(data, 8).map_err(|_| Error::Invalid("TSQ chunk header truncated"))
+            })
+            .map_err(|mut err| {
+                err.chunk_index = Some(self.index);
+                err
+            })
+            .and_then(|head| {
+                let id = [head[0], head[1], head[2], head[3]];
+                let track = (&id == b"TRK ").then_some(self.tracks);
+                let len = u32::from_le_bytes([head[4], head[5], head[6], head[7]]) as usize;
+                let data = cursor
+                    .field(|data| {
+                        take_slice(data, len)
+                            .map_err(|_| Error::Invalid("TSQ chunk length exceeds remaining data"))
+                    })
+                    .map_err(|err| err.in_chunk(id, self.index, track))?;
+                Ok(ChunkRef {
+                    id,
+                    index: self.index,
+                    track,
+                    offset,
+                    data,
+                })
+            });
+        if let Ok(chunk) = &result {
+            self.tracks += chunk.track.is_some() as usize;
+        }
+        self.index += 1;
+        self.failed = result.is_err();
+        Some(result)
+    }
+}
+
+impl<'a> ChunkIter<'a> {
+    /// Collect the remaining chunks, repairing damage instead of failing:
+    /// a chunk running past the end of the data is cut short and a partial
+    /// chunk header at the end is dropped. Each repair is added to
+    /// `diagnostics`.
+    pub fn salvage(mut self, diagnostics: &mut Vec<DecodeError>) -> Vec<ChunkRef<'a>> {
+        let mut chunks = Vec::new();
+        while let Some(chunk) = self.next() {
+            match chunk {
+                Ok(chunk) => chunks.push(chunk),
+                Err(err) => {
+                    // Only the data of a chunk with a complete header can be
+                    // left over; it is what remains of the input.
+                    if let (Some(id), Some(index)) = (err.chunk_id, err.chunk_index) {
+                        chunks.push(ChunkRef {
+                            id,
+                            index,
+                            track: err.track,
+                            offset: err.offset - 8,
+                            data: self.cursor.data,
+                        });
+                    }
+                    diagnostics.push(err);
+                }
+            }
+        }
+        chunks
+    }
+}
+
+/// Decode the header of a TSQ1 file and iterate over its chunks.
+pub fn chunks(data: &[u8]) -> Result<(Header, ChunkIter<'_>), DecodeError> {
+    let header = parse_header(data)?;
+    let chunks = ChunkIter {
+        cursor: Cursor::new(&data[HEADER_SIZE..], HEADER_SIZE),
+        index: 0,
+        tracks: 0,
+        failed: false,
+    };
+    Ok((header, chunks))
+}
+
+/// Decode the fixed file header at the start of `data`.
+pub(crate) fn parse_header(data: &[u8]) -> Result<Header, DecodeError> {
+    let at = DecodeError::new;
+    if data.len() < HEADER_SIZE {
+        return Err(at(data.len(), Error::Invalid("TSQ header truncated")));
+    }
+    if data[..4] != MAGIC {
+        return Err(at(0, Error::Invalid("TSQ magic missing")));
+    }
+    let version = u16::from_le_bytes([data[4], data[5]]);
+    if version != VERSION {
+        return Err(at(4, Error::Unsupported("unsupported TSQ version")));
+    }
+    let abs_unit = AbsUnit::from_u8(data[8]).ok_or(at(8, Error::Invalid("invalid AbsUnit")))?;
+    Ok(Header {
+        version,
+        ppq: u16::from_le_bytes([data[6], data[7]]),
+        abs_unit,
+        reserved: data[9],
+        track_count: u16::from_le_bytes([data[10], data[11]]),
+        flags: u16::from_le_bytes([data[12], data[13]]),
+    })
+}
+
+/// Remaining input and the offset of its first byte.
+#[derive(Debug, Clone)]
+struct Cursor<'a> {
+    data: &'a [u8],
+    /// Offset just past the end of `data`.
+    end: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(data: &'a [u8], base: usize) -> Self {
+        Cursor {
+            data,
+            end: base + data.len(),
+        }
+    }
+
+    fn offset(&self) -> usize {
+        self.end - self.data.len()
+    }
+
+    /// Decode one field, consuming it only on success. Errors report the
+    /// offset where the field starts.
+    fn field<T>(
+        &mut self,
+        read: impl FnOnce(&mut &'a [u8]) -> Result<T, Error>,
+    ) -> Result<T, DecodeError> {
+        let offset = self.offset();
+        let mut data = self.data;
+        let value = read(&mut data).map_err(|error| DecodeError::new(offset, error))?;
+        self.data = data;
+        Ok(value)
+    }
+
+    /// A `[Length:VLQ][Data]` payload.
+    fn payload(&mut self) -> Result<&'a [u8], DecodeError> {
+        let len = self.field(|data| {
+            usize::try_from(read_vlq(data)?).map_err(|_| Error::DataOverflow("payload too large"))
+        })?;
+        self.field(|data| take_slice(data, len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::Event;
+    use crate::sequence::{Sequence, Track};
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn borrows_events_with_cumulative_time() {
+        let mut seq = Sequence::new(480);
+        seq.tracks.push(Track::with_events(vec![
+            Event::musical(240, EventKind::Midi(MidiEvent::new(0x90, 60, 100))),
+            Event::absolute(1_000, EventKind::SysEx(vec![0xF0, 0x7E, 0xF7])),
+            Event::musical(240, EventKind::Meta(MetaEvent::new(0x01, b"hi".to_vec()))),
+            Event::absolute(500, EventKind::Custom(CustomEvent::new(0x42, vec![7]))),
+        ]));
+        let bytes = seq.to_vec().unwrap();
+
+        let (header, mut chunks) = chunks(&bytes).unwrap();
+        let track = chunks.next().unwrap().unwrap();
+        assert_eq!((&track.id, track.offset), (b"TRK ", HEADER_SIZE));
+        assert!(chunks.next().is_none());
+
+        let events: Vec<EventRef> = track.events(header.flags).map(Result::unwrap).collect();
+        let times: Vec<EventTime> = events.iter().map(|event| event.time).collect();
+        assert_eq!(
+            times,
+            [
+                EventTime::Musical(240),
+                EventTime::Absolute(1_000),
+                EventTime::Musical(480),
+                EventTime::Absolute(1_500),
+            ]
+        );
+        assert_eq!(events[0].offset, HEADER_SIZE + 8);
+        assert_eq!(
+            events[2].kind,
+            EventKindRef::Meta {
+                meta_type: 0x01,
+                data: b"hi"
+            }
+        );
+        let owned: Vec<Event> = events.iter().map(EventRef::to_event).collect();
+        assert_eq!(owned, seq.tracks[0].events);
+    }
+
+    #[test]
+    fn errors_report_exact_offsets() {
+        // Valid MIDI event, then a bad status byte at offset 5.
+        let data = [0x01, 0x00, 0x90, 0x3C, 0x64, 0x01, 0x00, 0x42];
+        let mut events = EventIter::new(&data, FLAG_MIDI_THREE_BYTES).with_base_offset(100);
+        assert!(events.next().unwrap().is_ok());
+        let err = events.next().unwrap().unwrap_err();
+        assert_eq!(err.offset, 107);
+        assert!(matches!(err.error, Error::Invalid(_)));
+        assert!(events.next().is_none());
+
+        // A payload running past the end fails where the payload starts.
+        let data = [0x03, 0x00, 0x05, 0xF0, 0x7E];
+        let err = EventIter::new(&data, 0).next().unwrap().unwrap_err();
+        assert_eq!(err.offset, 3);
+
+        let err = EventIter::new(&[0x05], 0).next().unwrap().unwrap_err();
+        assert!(matches!(
+            (err.offset, err.error),
+            (0, Error::Unsupported(_))
+        ));
+
+        let mut file = Sequence::new(480).to_vec().unwrap();
+        file.extend_from_slice(b"TRK \x10\0\0\0\x01");
+        let (_, mut chunks) = chunks(&file).unwrap();
+        let err = chunks.next().unwrap().unwrap_err();
+        assert_eq!(err.offset, HEADER_SIZE + 8);
+        assert_eq!(
+            (err.chunk_id, err.chunk_index, err.track, err.event),
+            (Some(*b"TRK "), Some(0), Some(0), None)
+        );
+    }
+}
@@ -3,16 +3,60 @@
 extern crate alloc;
 
 use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::convert::TryFrom;
 use core::fmt;
 
 use midly::num::{u14, u15, u24, u28, u4, u7};
 use midly::{
-    Format, Fps, Header, MetaMessage, MidiMessage, PitchBend, Smf, SmpteTime, Timing, TrackEvent,
-    TrackEventKind,
+    Format, Fps, Header as SmfHeader, MetaMessage, MidiMessage, PitchBend, Smf, SmpteTime, Timing,
+    TrackEvent, TrackEventKind,
 };
 
+pub mod custom;
+pub mod event;
+pub mod iter;
+pub mod marker;
+pub mod osc;
+mod provenance;
+mod read;
+#[cfg(feature = "std")]
+pub mod reader;
+pub mod sequence;
+pub mod sync;
+pub mod tempo;
+pub mod timecode;
+pub mod timeline;
+pub mod validate;
+mod write;
+#[cfg(feature = "std")]
+pub mod writer;
+
+pub use read::{read, read_lenient, read_lenient_with_registry, read_with_registry, Recovered};
+#[cfg(feature = "std")]
+pub use reader::{ChunkHeader, Reader};
+#[cfg(feature = "std")]
+pub use writer::Writer;
+
+pub use custom::{CustomCodec, CustomRegistry, CustomValue};
+pub use event::{
+    CustomEvent, Domain, Event, EventKind, EventTime, MetaEvent, MidiEvent, OscEvent, OscFormat,
+};
+pub use iter::{ChunkIter, ChunkRef, DecodeError, EventIter, EventKindRef, EventRef};
+pub use marker::{Locator, LocatorClass};
+pub use osc::{OscArg, OscBundle, OscMessage, OscPacket};
+pub use sequence::{
+    AbsUnit, Chunk, Header, MergedEvents, RawChunk, Sequence, Track, FLAG_MIDI_THREE_BYTES,
+    FLAG_SEQUENTIAL_TRACKS, FLAG_SYSEX_STATUS_IN_PAYLOAD,
+};
+pub use sync::{SyncAnchor, SyncMap};
+pub use tempo::{TempoEntry, TempoMap};
+pub use timecode::{SmpteFps, SmpteTiming};
+pub use timeline::{TimeSource, TimedEvent, Timeline};
+pub use validate::{validate, Report};
+
 /// Error type for TSQ1 conversions.
 #[derive(Debug)]
 pub enum Error {
@@ -24,6 +68,52 @@ pub enum Error {
     DataOverflow(&'static str),
     /// Invalid or malformed TSQ input data.
     Invalid(&'static str),
+    /// I/O failure while reading or writing a stream.
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
+    /// An error at a known position in TSQ1 input.
+    Located(Box<DecodeError>),
+}
+
+/// Category of an [`Error`], independent of where it occurred.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    Midi,
+    Unsupported,
+    DataOverflow,
+    Invalid,
+    Io,
+}
+
+impl Error {
+    /// The category of the error, looking through any location.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Midi(_) => ErrorKind::Midi,
+            Error::Unsupported(_) => ErrorKind::Unsupported,
+            Error::DataOverflow(_) => ErrorKind::DataOverflow,
+            Error::Invalid(_) => ErrorKind::Invalid,
+            #[cfg(feature = "std")]
+            Error::Io(_) => ErrorKind::Io,
+            Error::Located(located) => located.error.kind(),
+        }
+    }
+
+    /// Where in the input the error occurred, if known.
+    pub fn location(&self) -> Option<&DecodeError> {
+        match self {
+            Error::Located(located) => Some(located),
+            _ => None,
+        }
+    }
+
+    /// The error without its location.
+    pub fn cause(&self) -> &Error {
+        match self {
+            Error::Located(located) => located.error.cause(),
+            _ => self,
+        }
+    }
 }
 
 impl From<midly::Error> for Error {
@@ -39,34 +129,159 @@ impl fmt::Display for Error {
             Error::Unsupported(msg) => write!(f, "unsupported input: {msg}"),
             Error::DataOverflow(msg) => write!(f, "data overflow: {msg}"),
             Error::Invalid(msg) => write!(f, "invalid input: {msg}"),
+            #[cfg(feature = "std")]
+            Error::Io(e) => write!(f, "I/O error: {e}"),
+            Error::Located(located) => located.fmt(f),
         }
     }
 }
 
+#[cfg(feature = "std")]
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
 #[cfg(feature = "std")]
 impl std::error::Error for Error {}
 
+/// Options for [`convert_midi_to_tsq_vec_with_options`].
+#[derive(Debug, Clone, Default)]
+pub struct MidiToTsqOptions {
+    /// Move every tempo meta event (`0x51`) out of the SMF tracks into a
+    /// single `"TMAP"` chunk. [`convert_tsq_to_midi_vec`] re-injects the
+    /// entries into the first track. Ignored for Format 2 files, whose
+    /// patterns each keep their own tempo.
+    pub extract_tempo_map: bool,
+    /// Store program change and channel aftertouch as two bytes and leave
+    /// [`FLAG_MIDI_THREE_BYTES`] unset, as early encoders did. Off by
+    /// default: every MIDI event takes three bytes as spec §4.2 requires,
+    /// those two padded with a zero byte.
+    pub legacy_midi_layout: bool,
+    /// AbsUnit of the output when the SMF uses an SMPTE timecode division.
+    /// Such files become absolute-domain events, each placed at the nearest
+    /// unit to its exact time.
+    pub abs_unit: AbsUnit,
+    /// Append an `"SMFP"` chunk recording the layout of the SMF, so that
+    /// converting back reproduces the original file byte for byte as long as
+    /// its tracks, header and tempo map are unchanged. Only plain SMFs (not
+    /// RIFF-wrapped ones) can be archived.
+    pub archival: bool,
+}
+
+/// Options for [`convert_tsq_to_midi_vec_with_options`].
+#[derive(Debug, Clone, Default)]
+pub struct TsqToMidiOptions {
+    /// Write an SMF with this SMPTE timecode division instead of the PPQ
+    /// division. Absolute-domain events are then placed at the nearest tick;
+    /// musical events cannot be exported this way.
+    pub timecode: Option<SmpteTiming>,
+}
+
 /// Convert SMF (Standard MIDI File) bytes into a TSQ1 binary buffer.
 pub fn convert_midi_to_tsq_vec(midi_data: &[u8]) -> Result<Vec<u8>, Error> {
+    convert_midi_to_tsq_vec_with_options(midi_data, &MidiToTsqOptions::default())
+}
+
+/// Convert SMF bytes into a TSQ1 binary buffer using the given options.
+pub fn convert_midi_to_tsq_vec_with_options(
+    midi_data: &[u8],
+    options: &MidiToTsqOptions,
+) -> Result<Vec<u8>, Error> {
     let smf = Smf::parse(midi_data)?;
-    convert_smf_to_tsq(&smf)
+    let mut tsq = convert_smf_to_tsq(&smf, options)?;
+    if options.archival {
+        provenance::record(midi_data, &smf, &mut tsq)?;
+    }
+    Ok(tsq)
 }
 
 /// Convert TSQ1 bytes into a Standard MIDI File binary buffer.
+///
+/// The SMF uses the file's PPQ as its division, so every event must be in the
+/// musical domain; see [`convert_tsq_to_midi_vec_with_options`] for timecode
+/// output. OSC and custom events have no SMF representation and are dropped
+/// unvalidated; their delta times are folded into the following event so the
+/// remaining timing is unchanged.
+/// `"TMAP"` entries are written as tempo meta events into the first track, or
+/// into every pattern of a file with [`FLAG_SEQUENTIAL_TRACKS`].
+///
+/// The SMF format is Format 2 for files with [`FLAG_SEQUENTIAL_TRACKS`] and
+/// otherwise Format 0 or 1 depending on the number of tracks.
 pub fn convert_tsq_to_midi_vec(tsq_data: &[u8]) -> Result<Vec<u8>, Error> {
-    let smf = convert_tsq_to_smf(tsq_data)?;
+    convert_tsq_to_midi_vec_with_options(tsq_data, &TsqToMidiOptions::default())
+}
+
+/// Convert TSQ1 bytes into a Standard MIDI File using the given options.
+///
+/// With [`TsqToMidiOptions::timecode`] set, `"TMAP"` entries are not injected
+/// because a timecode SMF ignores tempo.
+///
+/// Files from an [archival](MidiToTsqOptions::archival) import reproduce the
+/// original SMF layout; tracks edited since then are encoded as usual.
+pub fn convert_tsq_to_midi_vec_with_options(
+    tsq_data: &[u8],
+    options: &TsqToMidiOptions,
+) -> Result<Vec<u8>, Error> {
+    let smf = convert_tsq_to_smf(tsq_data, options, None)?;
+    let mut out = Vec::new();
+    if !provenance::write(tsq_data, &smf, &mut out)? {
+        smf.write(&mut out)
+            .map_err(|_| Error::Invalid("failed to encode SMF"))?;
+    }
+    Ok(out)
+}
+
+/// Convert possibly damaged TSQ1 bytes into a Standard MIDI File, keeping
+/// every event that can be decoded.
+///
+/// Damage is handled as by [`read_lenient`] and reported in
+/// [`Recovered::diagnostics`]; a `TrackCount` that disagrees with the
+/// tracks found is reported too. Meta and SysEx events with a payload that
+/// cannot be exported are skipped. Archival provenance is not used.
+pub fn recover_tsq_to_midi_vec(
+    tsq_data: &[u8],
+    options: &TsqToMidiOptions,
+) -> Result<Recovered<Vec<u8>>, Error> {
+    let mut diagnostics = Vec::new();
+    let smf = convert_tsq_to_smf(tsq_data, options, Some(&mut diagnostics))?;
     let mut out = Vec::new();
     smf.write(&mut out)
         .map_err(|_| Error::Invalid("failed to encode SMF"))?;
-    Ok(out)
+    diagnostics.sort_by_key(|diagnostic| diagnostic.offset);
+    Ok(Recovered {
+        value: out,
+        diagnostics,
+    })
+}
+
+/// Rewrite TSQ1 bytes in the three-byte MIDI layout of spec §4.2.
+///
+/// Files without [`FLAG_MIDI_THREE_BYTES`] store program change and channel
+/// aftertouch as two bytes. Those messages gain a zero padding byte and the
+/// flag is set; everything else is kept, including the chunk layout. Files
+/// already using the layout pass through.
+pub fn migrate_midi_layout(tsq_data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut seq = read(tsq_data)?;
+    // The legacy layout reads back with `data2 = 0`, which becomes the padding.
+    seq.header.flags |= FLAG_MIDI_THREE_BYTES;
+    seq.to_vec()
 }
 
-const FLAG_SYSEX_STATUS_IN_PAYLOAD: u16 = 0x0001;
+/// PPQ written for SMPTE timecode imports, whose events are all absolute.
+const TIMECODE_IMPORT_PPQ: u16 = 480;
 
-fn convert_smf_to_tsq(smf: &Smf<'_>) -> Result<Vec<u8>, Error> {
-    let ppq = match smf.header.timing {
-        Timing::Metrical(metrical) => metrical.as_int(),
-        Timing::Timecode(_, _) => return Err(Error::Unsupported("SMPTE timecode timing")),
+fn convert_smf_to_tsq(smf: &Smf<'_>, options: &MidiToTsqOptions) -> Result<Vec<u8>, Error> {
+    let (ppq, abs_unit, timecode) = match smf.header.timing {
+        Timing::Metrical(metrical) => (metrical.as_int(), AbsUnit::Microseconds, None),
+        Timing::Timecode(fps, ticks_per_frame) => {
+            if ticks_per_frame == 0 {
+                return Err(Error::Invalid("SMPTE division has zero ticks per frame"));
+            }
+            let timing = SmpteTiming::new(smpte_fps(fps), ticks_per_frame);
+            (TIMECODE_IMPORT_PPQ, options.abs_unit, Some(timing))
+        }
     };
 
     if smf.tracks.len() > u16::MAX as usize {
@@ -83,190 +298,344 @@ fn convert_smf_to_tsq(smf: &Smf<'_>) -> Result<Vec<u8>, Error> {
     });
 
     let mut out = Vec::new();
-    let flags = if sysex_present {
+    let mut flags = if sysex_present {
         FLAG_SYSEX_STATUS_IN_PAYLOAD
     } else {
         0
     };
-    write_header(&mut out, ppq as u16, smf.tracks.len() as u16, flags);
+    if !options.legacy_midi_layout {
+        flags |= FLAG_MIDI_THREE_BYTES;
+    }
+    let sequential = smf.header.format == Format::Sequential;
+    if sequential {
+        flags |= FLAG_SEQUENTIAL_TRACKS;
+    }
+    write_header(&mut out, ppq, abs_unit, smf.tracks.len() as u16, flags);
 
+    let mut tempo_map = TempoMap::new();
     for track in smf.tracks.iter() {
         let mut track_buf = Vec::new();
-        for event in track {
-            encode_event(
-                event.delta.as_int() as u64,
-                &event.kind,
-                &mut track_buf,
-                sysex_present,
-            )?;
+        let mut tick = 0u64;
+        if let Some(timing) = timecode {
+            let mut previous = 0u64;
+            for event in track {
+                tick += event.delta.as_int() as u64;
+                let time_abs = timing
+                    .tick_to_abs(tick, abs_unit)
+                    .ok_or(Error::DataOverflow("timecode position exceeds u64"))?;
+                let delta = time_abs - previous;
+                previous = time_abs;
+                encode_event(delta, Domain::Absolute, &event.kind, &mut track_buf, flags)?;
+            }
+            push_track_chunk(&mut out, &track_buf)?;
+            continue;
         }
-        if track_buf.len() > u32::MAX as usize {
-            return Err(Error::DataOverflow("track chunk too large"));
+        // Delta of extracted tempo events, carried into the next event.
+        let mut carried = 0u64;
+        for event in track {
+            let delta = event.delta.as_int() as u64;
+            tick += delta;
+            if options.extract_tempo_map && !sequential {
+                if let TrackEventKind::Meta(MetaMessage::Tempo(tempo)) = event.kind {
+                    tempo_map.insert(TempoEntry {
+                        tick,
+                        us_per_qn: tempo.as_int(),
+                    });
+                    carried += delta;
+                    continue;
+                }
+            }
+            let delta = carried + delta;
+            encode_event(delta, Domain::Musical, &event.kind, &mut track_buf, flags)?;
+            carried = 0;
         }
-        out.extend_from_slice(b"TRK ");
-        out.extend_from_slice(&(track_buf.len() as u32).to_le_bytes());
-        out.extend_from_slice(&track_buf);
+        push_track_chunk(&mut out, &track_buf)?;
     }
 
-    Ok(out)
-}
-
-fn convert_tsq_to_smf<'a>(tsq_data: &'a [u8]) -> Result<Smf<'a>, Error> {
-    const HEADER_SIZE: usize = 14;
-    if tsq_data.len() < HEADER_SIZE {
-        return Err(Error::Invalid("TSQ header truncated"));
+    if !tempo_map.is_empty() {
+        let mut chunk_buf = Vec::new();
+        let chunk = Chunk::TempoMap(tempo_map);
+        write::encode_chunk(&chunk, &mut chunk_buf)?;
+        write::push_chunk(&mut out, &chunk.id(), &chunk_buf)?;
     }
 
-    if &tsq_data[..4] != b"TSQ1" {
-        return Err(Error::Invalid("TSQ magic missing"));
-    }
-
-    let version = u16::from_le_bytes([tsq_data[4], tsq_data[5]]);
-    if version != 1 {
-        return Err(Error::Unsupported("unsupported TSQ version"));
-    }
+    Ok(out)
+}
 
-    let ppq = u16::from_le_bytes([tsq_data[6], tsq_data[7]]);
-    let abs_unit = tsq_data[8];
-    if abs_unit != 0 {
-        return Err(Error::Unsupported("absolute timing domain not supported"));
+fn push_track_chunk(out: &mut Vec<u8>, track_buf: &[u8]) -> Result<(), Error> {
+    if track_buf.len() > u32::MAX as usize {
+        return Err(Error::DataOverflow("track chunk too large"));
     }
+    out.extend_from_slice(b"TRK ");
+    out.extend_from_slice(&(track_buf.len() as u32).to_le_bytes());
+    out.extend_from_slice(track_buf);
+    Ok(())
+}
 
-    let track_count = u16::from_le_bytes([tsq_data[10], tsq_data[11]]);
-    let flags = u16::from_le_bytes([tsq_data[12], tsq_data[13]]);
-
-    let timing =
-        u15::try_from(ppq).ok_or(Error::Unsupported("PPQ exceeds SMF metrical timing range"))?;
-    let format = if track_count <= 1 {
+/// With `diagnostics`, damaged input is salvaged and each repair recorded
+/// there.
+fn convert_tsq_to_smf<'a>(
+    tsq_data: &'a [u8],
+    options: &TsqToMidiOptions,
+    mut diagnostics: Option<&mut Vec<DecodeError>>,
+) -> Result<Smf<'a>, Error> {
+    let (header, chunks) = iter::chunks(tsq_data)?;
+    let ppq = header.ppq;
+    let abs_unit = header.abs_unit;
+    let track_count = header.track_count;
+    let flags = header.flags;
+
+    let timing = match options.timecode {
+        None => Timing::Metrical(
+            u15::try_from(ppq)
+                .ok_or(Error::Unsupported("PPQ exceeds SMF metrical timing range"))?,
+        ),
+        Some(timecode) => {
+            if timecode.ticks_per_frame == 0 {
+                return Err(Error::Invalid("SMPTE division has zero ticks per frame"));
+            }
+            Timing::Timecode(midly_fps(timecode.fps), timecode.ticks_per_frame)
+        }
+    };
+    let sequential = flags & FLAG_SEQUENTIAL_TRACKS != 0;
+    let format = if sequential {
+        Format::Sequential
+    } else if track_count <= 1 {
         Format::SingleTrack
     } else {
         Format::Parallel
     };
 
-    let mut cursor = &tsq_data[HEADER_SIZE..];
     let mut tracks: Vec<Vec<TrackEvent<'a>>> = Vec::new();
+    let mut tempo_map = TempoMap::new();
 
-    while !cursor.is_empty() {
-        if cursor.len() < 8 {
-            return Err(Error::Invalid("TSQ chunk header truncated"));
-        }
-        let id = &cursor[..4];
-        let len = u32::from_le_bytes([cursor[4], cursor[5], cursor[6], cursor[7]]) as usize;
-        cursor = &cursor[8..];
-        if cursor.len() < len {
-            return Err(Error::Invalid("TSQ chunk length exceeds remaining data"));
+    let chunks = match diagnostics.as_deref_mut() {
+        Some(diagnostics) => chunks.salvage(diagnostics),
+        None => chunks.collect::<Result<_, _>>()?,
+    };
+    for chunk in chunks {
+        if &chunk.id == b"TRK " {
+            let events = parse_track(chunk, flags, diagnostics.as_deref_mut())?;
+            tracks.push(place_events(events, options.timecode, abs_unit)?);
+        } else if &chunk.id == b"TMAP" && options.timecode.is_none() {
+            match read::read_tempo_map(chunk.data) {
+                Ok(map) => {
+                    for entry in map.entries {
+                        tempo_map.insert(entry);
+                    }
+                }
+                Err(err) => {
+                    let err = chunk.error_at(chunk.offset + 8, err);
+                    match diagnostics.as_deref_mut() {
+                        Some(diagnostics) => diagnostics.push(err),
+                        None => return Err(err.into()),
+                    }
+                }
+            }
         }
-        let chunk_data = &cursor[..len];
-        cursor = &cursor[len..];
+    }
 
-        if id == b"TRK " {
-            let events = parse_track(chunk_data, flags & FLAG_SYSEX_STATUS_IN_PAYLOAD != 0)?;
-            tracks.push(events);
+    if tracks.len() != track_count as usize {
+        let err = Error::Invalid("track count mismatch");
+        match diagnostics {
+            Some(diagnostics) => diagnostics.push(DecodeError::new(10, err)),
+            None => return Err(err),
         }
     }
 
-    if tracks.len() != track_count as usize {
-        return Err(Error::Invalid("track count mismatch"));
+    if !tempo_map.is_empty() {
+        if tracks.is_empty() {
+            tracks.push(vec![TrackEvent {
+                delta: 0.into(),
+                kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+            }]);
+        }
+        let patterns = if sequential { tracks.len() } else { 1 };
+        for track in &mut tracks[..patterns] {
+            inject_tempo_map(track, &tempo_map)?;
+        }
     }
 
     Ok(Smf {
-        header: Header::new(format, Timing::Metrical(timing)),
+        header: SmfHeader::new(format, timing),
         tracks,
     })
 }
 
+/// Merge sorted tempo map entries into `track` as tempo meta events.
+///
+/// At a shared tick, tempo events follow existing meta events and precede
+/// channel and SysEx events. A trailing end-of-track event stays last.
+fn inject_tempo_map(track: &mut Vec<TrackEvent<'_>>, tempo_map: &TempoMap) -> Result<(), Error> {
+    let mut events = core::mem::take(track);
+    let end_of_track = match events.last() {
+        Some(TrackEvent {
+            kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+            ..
+        }) => events.pop(),
+        _ => None,
+    };
+
+    let mut timed = Vec::with_capacity(events.len() + tempo_map.entries.len() + 1);
+    let mut entries = tempo_map.entries.iter().peekable();
+    let mut tick = 0u64;
+    for event in events {
+        tick += event.delta.as_int() as u64;
+        let is_meta = matches!(event.kind, TrackEventKind::Meta(_));
+        while let Some(entry) = entries.next_if(|e| e.tick < tick || (e.tick == tick && !is_meta)) {
+            timed.push((entry.tick, tempo_event_kind(entry.us_per_qn)?));
+        }
+        timed.push((tick, event.kind));
+    }
+    for entry in entries {
+        timed.push((entry.tick, tempo_event_kind(entry.us_per_qn)?));
+    }
+    if let Some(end) = end_of_track {
+        let last_tick = timed.last().map_or(0, |(tick, _)| *tick);
+        timed.push((last_tick.max(tick + end.delta.as_int() as u64), end.kind));
+    }
+
+    let mut previous = 0u64;
+    for (tick, kind) in timed {
+        let delta = u32::try_from(tick - previous)
+            .ok()
+            .and_then(u28::try_from)
+            .ok_or(Error::DataOverflow("delta exceeds MIDI limits"))?;
+        previous = tick;
+        track.push(TrackEvent { delta, kind });
+    }
+    Ok(())
+}
+
+fn tempo_event_kind<'a>(us_per_qn: u32) -> Result<TrackEventKind<'a>, Error> {
+    let tempo = u24::try_from(us_per_qn).ok_or(Error::DataOverflow("tempo exceeds 24 bits"))?;
+    Ok(TrackEventKind::Meta(MetaMessage::Tempo(tempo)))
+}
+
+/// Decode a `"TRK "` chunk into SMF events at their cumulative position.
+///
+/// Events with no SMF representation are skipped; since positions are
+/// cumulative, the timing of the remaining events is preserved.
+///
+/// With `diagnostics`, errors are recorded there instead: an event that
+/// cannot be exported is skipped and undecodable data ends the track.
 fn parse_track<'a>(
-    mut data: &'a [u8],
-    sysex_with_status: bool,
-) -> Result<Vec<TrackEvent<'a>>, Error> {
+    chunk: ChunkRef<'a>,
+    flags: u16,
+    mut diagnostics: Option<&mut Vec<DecodeError>>,
+) -> Result<Vec<(EventTime, TrackEventKind<'a>)>, Error> {
+    let sysex_with_status = flags & FLAG_SYSEX_STATUS_IN_PAYLOAD != 0;
     let mut events = Vec::new();
-    while !data.is_empty() {
-        let header = read_u8(&mut data)?;
-        let domain = header >> 7;
-        if domain != 0 {
-            return Err(Error::Unsupported(
-                "absolute domain events are not supported",
-            ));
+    for (index, event) in chunk.events(flags).enumerate() {
+        let result = event.and_then(|event| {
+            match event.kind {
+                EventKindRef::Osc { .. } | EventKindRef::Custom { .. } => Ok(None),
+                EventKindRef::Midi(midi) => midi_event_kind(midi).map(Some),
+                EventKindRef::Meta { meta_type, data } => {
+                    meta_from_payload(meta_type, data).map(|meta| Some(TrackEventKind::Meta(meta)))
+                }
+                EventKindRef::SysEx(data) => sysex_event_kind(data, sysex_with_status).map(Some),
+            }
+            .map(|kind| kind.map(|kind| (event.time, kind)))
+            .map_err(|err| chunk.error_at(event.offset, err).at_event(index))
+        });
+        match (result, diagnostics.as_deref_mut()) {
+            (Ok(Some(event)), _) => events.push(event),
+            (Ok(None), _) => {}
+            (Err(err), Some(diagnostics)) => diagnostics.push(err),
+            (Err(err), None) => return Err(err.into()),
         }
-        let kind = header & 0x7F;
-        let delta = read_vlq(&mut data)?;
-        let delta_u32 =
-            u32::try_from(delta).map_err(|_| Error::DataOverflow("delta exceeds u32"))?;
-        let delta =
-            u28::try_from(delta_u32).ok_or(Error::DataOverflow("delta exceeds MIDI limits"))?;
-
-        let event_kind = match kind {
-            0x01 => parse_midi_event(&mut data)?,
-            0x02 => parse_meta_event(&mut data)?,
-            0x03 => parse_sysex_event(&mut data, sysex_with_status)?,
-            0x7E => return Err(Error::Unsupported("custom events are not supported")),
-            _ => return Err(Error::Unsupported("unknown musical event type")),
+    }
+    Ok(events)
+}
+
+/// Turn positioned events into SMF delta times for the output division.
+///
+/// The PPQ division takes musical events as they are. A timecode division
+/// takes absolute events, each rounded to the nearest tick.
+fn place_events<'a>(
+    events: Vec<(EventTime, TrackEventKind<'a>)>,
+    timecode: Option<SmpteTiming>,
+    abs_unit: AbsUnit,
+) -> Result<Vec<TrackEvent<'a>>, Error> {
+    let mut placed = Vec::with_capacity(events.len());
+    let mut previous = 0u64;
+    for (time, kind) in events {
+        let tick = match (time, timecode) {
+            (EventTime::Musical(tick), None) => tick,
+            (EventTime::Absolute(time_abs), Some(timing)) => timing
+                .abs_to_tick(time_abs, abs_unit)
+                .ok_or(Error::DataOverflow("timecode position exceeds u64"))?,
+            (EventTime::Absolute(_), None) => {
+                return Err(Error::Unsupported(
+                    "absolute domain events need a timecode division",
+                ))
+            }
+            (EventTime::Musical(_), Some(_)) => {
+                return Err(Error::Unsupported(
+                    "musical events cannot use a timecode division",
+                ))
+            }
         };
+        let delta = u32::try_from(tick - previous)
+            .ok()
+            .and_then(u28::try_from)
+            .ok_or(Error::DataOverflow("delta exceeds MIDI limits"))?;
+        previous = tick;
+        placed.push(TrackEvent { delta, kind });
+    }
+    Ok(placed)
+}
 
-        events.push(TrackEvent {
-            delta,
-            kind: event_kind,
-        });
+fn smpte_fps(fps: Fps) -> SmpteFps {
+    match fps {
+        Fps::Fps24 => SmpteFps::Fps24,
+        Fps::Fps25 => SmpteFps::Fps25,
+        Fps::Fps29 => SmpteFps::Fps29_97Drop,
+        Fps::Fps30 => SmpteFps::Fps30,
     }
-    Ok(events)
 }
 
-fn parse_midi_event<'a>(data: &mut &'a [u8]) -> Result<TrackEventKind<'a>, Error> {
-    let status = read_u8(data)?;
-    if !(0x80..=0xEF).contains(&status) {
-        return Err(Error::Invalid("invalid MIDI status byte"));
+fn midly_fps(fps: SmpteFps) -> Fps {
+    match fps {
+        SmpteFps::Fps24 => Fps::Fps24,
+        SmpteFps::Fps25 => Fps::Fps25,
+        SmpteFps::Fps29_97Drop => Fps::Fps29,
+        SmpteFps::Fps30 => Fps::Fps30,
     }
+}
+
+fn midi_event_kind<'a>(midi: MidiEvent) -> Result<TrackEventKind<'a>, Error> {
+    let MidiEvent {
+        status,
+        data1,
+        data2,
+    } = midi;
     let channel = u4::try_from(status & 0x0F).ok_or(Error::Invalid("invalid MIDI channel"))?;
-    let high_nibble = status >> 4;
-    let message = match high_nibble {
-        0x8 => {
-            let data1 = read_u8(data)?;
-            let data2 = read_u8(data)?;
-            MidiMessage::NoteOff {
-                key: u7::try_from(data1).ok_or(Error::Invalid("note key out of range"))?,
-                vel: u7::try_from(data2).ok_or(Error::Invalid("velocity out of range"))?,
-            }
-        }
-        0x9 => {
-            let data1 = read_u8(data)?;
-            let data2 = read_u8(data)?;
-            MidiMessage::NoteOn {
-                key: u7::try_from(data1).ok_or(Error::Invalid("note key out of range"))?,
-                vel: u7::try_from(data2).ok_or(Error::Invalid("velocity out of range"))?,
-            }
-        }
-        0xA => {
-            let data1 = read_u8(data)?;
-            let data2 = read_u8(data)?;
-            MidiMessage::Aftertouch {
-                key: u7::try_from(data1).ok_or(Error::Invalid("note key out of range"))?,
-                vel: u7::try_from(data2).ok_or(Error::Invalid("velocity out of range"))?,
-            }
-        }
-        0xB => {
-            let data1 = read_u8(data)?;
-            let data2 = read_u8(data)?;
-            MidiMessage::Controller {
-                controller: u7::try_from(data1).ok_or(Error::Invalid("controller out of range"))?,
-                value: u7::try_from(data2)
-                    .ok_or(Error::Invalid("controller value out of range"))?,
-            }
-        }
-        0xC => {
-            let program = read_u8(data)?;
-            MidiMessage::ProgramChange {
-                program: u7::try_from(program).ok_or(Error::Invalid("program out of range"))?,
-            }
-        }
-        0xD => {
-            let vel = read_u8(data)?;
-            MidiMessage::ChannelAftertouch {
-                vel: u7::try_from(vel).ok_or(Error::Invalid("aftertouch velocity out of range"))?,
-            }
-        }
+    let message = match status >> 4 {
+        0x8 => MidiMessage::NoteOff {
+            key: u7::try_from(data1).ok_or(Error::Invalid("note key out of range"))?,
+            vel: u7::try_from(data2).ok_or(Error::Invalid("velocity out of range"))?,
+        },
+        0x9 => MidiMessage::NoteOn {
+            key: u7::try_from(data1).ok_or(Error::Invalid("note key out of range"))?,
+            vel: u7::try_from(data2).ok_or(Error::Invalid("velocity out of range"))?,
+        },
+        0xA => MidiMessage::Aftertouch {
+            key: u7::try_from(data1).ok_or(Error::Invalid("note key out of range"))?,
+            vel: u7::try_from(data2).ok_or(Error::Invalid("velocity out of range"))?,
+        },
+        0xB => MidiMessage::Controller {
+            controller: u7::try_from(data1).ok_or(Error::Invalid("controller out of range"))?,
+            value: u7::try_from(data2).ok_or(Error::Invalid("controller value out of range"))?,
+        },
+        0xC => MidiMessage::ProgramChange {
+            program: u7::try_from(data1).ok_or(Error::Invalid("program out of range"))?,
+        },
+        0xD => MidiMessage::ChannelAftertouch {
+            vel: u7::try_from(data1).ok_or(Error::Invalid("aftertouch velocity out of range"))?,
+        },
         0xE => {
-            let data1 = read_u8(data)?;
-            let data2 = read_u8(data)?;
             let lsb = u7::try_from(data1).ok_or(Error::Invalid("pitch bend LSB out of range"))?;
             let msb = u7::try_from(data2).ok_or(Error::Invalid("pitch bend MSB out of range"))?;
             let raw = ((msb.as_int() as u16) << 7) | lsb.as_int() as u16;
@@ -281,24 +650,7 @@ fn parse_midi_event<'a>(data: &mut &'a [u8]) -> Result<TrackEventKind<'a>, Error
     Ok(TrackEventKind::Midi { channel, message })
 }
 
-fn parse_meta_event<'a>(data: &mut &'a [u8]) -> Result<TrackEventKind<'a>, Error> {
-    let ty = read_u8(data)?;
-    let len = read_vlq(data)?;
-    let len_usize =
-        usize::try_from(len).map_err(|_| Error::DataOverflow("meta payload too large"))?;
-    let payload = take_slice(data, len_usize)?;
-    let meta = meta_from_payload(ty, payload)?;
-    Ok(TrackEventKind::Meta(meta))
-}
-
-fn parse_sysex_event<'a>(
-    data: &mut &'a [u8],
-    has_status: bool,
-) -> Result<TrackEventKind<'a>, Error> {
-    let len = read_vlq(data)?;
-    let len_usize =
-        usize::try_from(len).map_err(|_| Error::DataOverflow("sysex payload too large"))?;
-    let payload = take_slice(data, len_usize)?;
+fn sysex_event_kind(payload: &[u8], has_status: bool) -> Result<TrackEventKind<'_>, Error> {
     if has_status {
         let (status, body) = payload
             .split_first()
@@ -335,7 +687,7 @@ fn meta_from_payload<'a>(ty: u8, data: &'a [u8]) -> Result<MetaMessage<'a>, Erro
         0x09 => DeviceName(data),
         0x20 => {
             let channel = *data
-                .get(0)
+                .first()
                 .ok_or(Error::Invalid("missing MIDI channel value"))?;
             let channel =
                 u4::try_from(channel).ok_or(Error::Invalid("MIDI channel out of range"))?;
@@ -343,7 +695,7 @@ fn meta_from_payload<'a>(ty: u8, data: &'a [u8]) -> Result<MetaMessage<'a>, Erro
         }
         0x21 => {
             let port = *data
-                .get(0)
+                .first()
                 .ok_or(Error::Invalid("missing MIDI port value"))?;
             let port = u7::try_from(port).ok_or(Error::Invalid("MIDI port out of range"))?;
             MidiPort(port)
@@ -402,7 +754,7 @@ fn meta_from_payload<'a>(ty: u8, data: &'a [u8]) -> Result<MetaMessage<'a>, Erro
     })
 }
 
-fn read_u8<'a>(data: &mut &'a [u8]) -> Result<u8, Error> {
+fn read_u8(data: &mut &[u8]) -> Result<u8, Error> {
     if data.is_empty() {
         return Err(Error::Invalid("unexpected end of track data"));
     }
@@ -420,7 +772,13 @@ fn take_slice<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
     Ok(prefix)
 }
 
-fn read_vlq<'a>(data: &mut &'a [u8]) -> Result<u64, Error> {
+fn read_len_prefixed<'a>(data: &mut &'a [u8]) -> Result<&'a [u8], Error> {
+    let len = read_vlq(data)?;
+    let len = usize::try_from(len).map_err(|_| Error::DataOverflow("payload too large"))?;
+    take_slice(data, len)
+}
+
+fn read_vlq(data: &mut &[u8]) -> Result<u64, Error> {
     let mut value = 0u64;
     let mut read = 0usize;
     loop {
@@ -437,11 +795,11 @@ fn read_vlq<'a>(data: &mut &'a [u8]) -> Result<u64, Error> {
     Ok(value)
 }
 
-fn write_header(out: &mut Vec<u8>, ppq: u16, track_count: u16, flags: u16) {
+fn write_header(out: &mut Vec<u8>, ppq: u16, abs_unit: AbsUnit, track_count: u16, flags: u16) {
     out.extend_from_slice(b"TSQ1");
     out.extend_from_slice(&1u16.to_le_bytes());
     out.extend_from_slice(&ppq.to_le_bytes());
-    out.push(0); // AbsUnit = microseconds
+    out.push(abs_unit.as_u8());
     out.push(0); // Reserved
     out.extend_from_slice(&track_count.to_le_bytes());
     out.extend_from_slice(&flags.to_le_bytes());
@@ -449,15 +807,20 @@ fn write_header(out: &mut Vec<u8>, ppq: u16, track_count: u16, flags: u16) {
 
 fn encode_event(
     delta: u64,
+    domain: Domain,
     kind: &TrackEventKind<'_>,
     out: &mut Vec<u8>,
-    sysex_with_status: bool,
+    flags: u16,
 ) -> Result<(), Error> {
-    const DOMAIN_MUSICAL: u8 = 0;
+    let domain = match domain {
+        Domain::Musical => 0x00,
+        Domain::Absolute => 0x80,
+    };
+    let sysex_with_status = flags & FLAG_SYSEX_STATUS_IN_PAYLOAD != 0;
 
     match kind {
         TrackEventKind::Midi { channel, message } => {
-            out.push(DOMAIN_MUSICAL | 0x01);
+            out.push(domain | 0x01);
             write_vlq(delta, out);
             let status = midi_status_byte(*channel, message);
             let (data1, data2) = midi_message_bytes(message);
@@ -465,10 +828,12 @@ fn encode_event(
             out.push(data1);
             if let Some(d2) = data2 {
                 out.push(d2);
+            } else if flags & FLAG_MIDI_THREE_BYTES != 0 {
+                out.push(0);
             }
         }
         TrackEventKind::SysEx(data) => {
-            out.push(DOMAIN_MUSICAL | 0x03);
+            out.push(domain | 0x03);
             write_vlq(delta, out);
             let len = data.len() + usize::from(sysex_with_status);
             write_vlq(len as u64, out);
@@ -478,7 +843,7 @@ fn encode_event(
             out.extend_from_slice(data);
         }
         TrackEventKind::Escape(data) => {
-            out.push(DOMAIN_MUSICAL | 0x03);
+            out.push(domain | 0x03);
             write_vlq(delta, out);
             let len = data.len() + usize::from(sysex_with_status);
             write_vlq(len as u64, out);
@@ -488,7 +853,7 @@ fn encode_event(
             out.extend_from_slice(data);
         }
         TrackEventKind::Meta(meta) => {
-            out.push(DOMAIN_MUSICAL | 0x02);
+            out.push(domain | 0x02);
             write_vlq(delta, out);
             let (ty, payload) = meta_payload(meta);
             out.push(ty);
@@ -612,6 +977,10 @@ pub mod ffi {
     /// Convert SMF bytes into TSQ1 format, allocating a new buffer for the result.
     ///
     /// The caller is responsible for freeing the resulting buffer with [`tsq1_buffer_free`].
+    ///
+    /// # Safety
+    ///
+    /// `midi_ptr` must point to `midi_len` readable bytes and `out` must be valid for writes.
     #[no_mangle]
     pub unsafe extern "C" fn tsq1_mid_to_tsq(
         midi_ptr: *const u8,
@@ -646,6 +1015,10 @@ pub mod ffi {
     }
 
     /// Release a buffer produced by [`tsq1_mid_to_tsq`].
+    ///
+    /// # Safety
+    ///
+    /// `buf` must have been returned by this library and not freed already.
     #[no_mangle]
     pub unsafe extern "C" fn tsq1_buffer_free(buf: Tsq1Buffer) {
         if buf.ptr.is_null() {
@@ -679,8 +1052,13 @@ mod tests {
     ) -> Vec<u8> {
         assert_eq!(events.len(), deltas.len());
         let mut track = Vec::new();
+        let flags = if sysex_with_status {
+            super::FLAG_SYSEX_STATUS_IN_PAYLOAD
+        } else {
+            0
+        };
         for (event, delta) in events.iter().zip(deltas.iter()) {
-            encode_event(*delta, event, &mut track, sysex_with_status)
+            encode_event(*delta, Domain::Musical, event, &mut track, flags)
                 .expect("encode_event should succeed");
         }
         track
@@ -688,7 +1066,7 @@ mod tests {
 
     fn tsq_with_single_track(track_data: &[u8], ppq: u16, flags: u16) -> Vec<u8> {
         let mut tsq = Vec::new();
-        write_header(&mut tsq, ppq, 1, flags);
+        write_header(&mut tsq, ppq, AbsUnit::Microseconds, 1, flags);
         tsq.extend_from_slice(b"TRK ");
         tsq.extend_from_slice(&(track_data.len() as u32).to_le_bytes());
         tsq.extend_from_slice(track_data);
@@ -793,7 +1171,8 @@ mod tests {
         let track = build_tsq_track(&events, &deltas, false);
         let tsq = tsq_with_single_track(&track, 960, 0);
 
-        let smf = super::convert_tsq_to_smf(&tsq).expect("conversion succeeds");
+        let smf = super::convert_tsq_to_smf(&tsq, &TsqToMidiOptions::default(), None)
+            .expect("conversion succeeds");
         assert_eq!(smf.tracks.len(), 1);
         let track = &smf.tracks[0];
         assert_eq!(track.len(), 3);
@@ -851,7 +1230,8 @@ mod tests {
         let track = build_tsq_track(&events, &deltas, true);
         let tsq = tsq_with_single_track(&track, 960, super::FLAG_SYSEX_STATUS_IN_PAYLOAD);
 
-        let smf = super::convert_tsq_to_smf(&tsq).expect("conversion succeeds");
+        let smf = super::convert_tsq_to_smf(&tsq, &TsqToMidiOptions::default(), None)
+            .expect("conversion succeeds");
         assert_eq!(smf.tracks.len(), 1);
         let track = &smf.tracks[0];
         assert_eq!(track.len(), 3);
@@ -890,7 +1270,7 @@ mod tests {
             },
         ];
         let smf = Smf {
-            header: Header::new(Format::SingleTrack, Timing::Metrical(u15::from(480u16))),
+            header: SmfHeader::new(Format::SingleTrack, Timing::Metrical(u15::from(480u16))),
             tracks: vec![track],
         };
 
@@ -962,7 +1342,7 @@ mod tests {
         ];
 
         let smf = Smf {
-            header: Header::new(Format::SingleTrack, Timing::Metrical(u15::from(960u16))),
+            header: SmfHeader::new(Format::SingleTrack, Timing::Metrical(u15::from(960u16))),
             tracks: vec![track],
         };
 
@@ -980,54 +1360,56 @@ mod tests {
         let lead_name: &[u8] = b"Lead";
         let tempo = u24::from(500_000u32);
 
-        let mut track0 = Vec::new();
-        track0.push(TrackEvent {
-            delta: 0.into(),
-            kind: TrackEventKind::Meta(MetaMessage::TrackName(conductor_name)),
-        });
-        track0.push(TrackEvent {
-            delta: 0.into(),
-            kind: TrackEventKind::Meta(MetaMessage::TimeSignature(4, 2, 24, 8)),
-        });
-        track0.push(TrackEvent {
-            delta: 0.into(),
-            kind: TrackEventKind::Meta(MetaMessage::Tempo(tempo)),
-        });
-        track0.push(TrackEvent {
-            delta: 0.into(),
-            kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
-        });
+        let track0 = vec![
+            TrackEvent {
+                delta: 0.into(),
+                kind: TrackEventKind::Meta(MetaMessage::TrackName(conductor_name)),
+            },
+            TrackEvent {
+                delta: 0.into(),
+                kind: TrackEventKind::Meta(MetaMessage::TimeSignature(4, 2, 24, 8)),
+            },
+            TrackEvent {
+                delta: 0.into(),
+                kind: TrackEventKind::Meta(MetaMessage::Tempo(tempo)),
+            },
+            TrackEvent {
+                delta: 0.into(),
+                kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+            },
+        ];
 
         let channel = u4::try_from(1).unwrap();
         let key = u7::try_from(67).unwrap();
         let velocity = u7::try_from(110).unwrap();
 
-        let mut track1 = Vec::new();
-        track1.push(TrackEvent {
-            delta: 0.into(),
-            kind: TrackEventKind::Meta(MetaMessage::TrackName(lead_name)),
-        });
-        track1.push(TrackEvent {
-            delta: u28::from(120u32),
-            kind: TrackEventKind::Midi {
-                channel,
-                message: MidiMessage::NoteOn { key, vel: velocity },
+        let track1 = vec![
+            TrackEvent {
+                delta: 0.into(),
+                kind: TrackEventKind::Meta(MetaMessage::TrackName(lead_name)),
             },
-        });
-        track1.push(TrackEvent {
-            delta: u28::from(480u32),
-            kind: TrackEventKind::Midi {
-                channel,
-                message: MidiMessage::NoteOff { key, vel: velocity },
+            TrackEvent {
+                delta: u28::from(120u32),
+                kind: TrackEventKind::Midi {
+                    channel,
+                    message: MidiMessage::NoteOn { key, vel: velocity },
+                },
             },
-        });
-        track1.push(TrackEvent {
-            delta: 0.into(),
-            kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
-        });
+            TrackEvent {
+                delta: u28::from(480u32),
+                kind: TrackEventKind::Midi {
+                    channel,
+                    message: MidiMessage::NoteOff { key, vel: velocity },
+                },
+            },
+            TrackEvent {
+                delta: 0.into(),
+                kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+            },
+        ];
 
         let smf = Smf {
-            header: Header::new(Format::Parallel, Timing::Metrical(u15::from(960u16))),
+            header: SmfHeader::new(Format::Parallel, Timing::Metrical(u15::from(960u16))),
             tracks: vec![track0, track1],
         };
 
@@ -1050,7 +1432,7 @@ mod tests {
         assert_eq!(roundtrip_track0.len(), 4);
         match &roundtrip_track0[0].kind {
             TrackEventKind::Meta(MetaMessage::TrackName(name)) => {
-                assert_eq!(name.as_ref(), b"Conductor")
+                assert_eq!(*name, b"Conductor")
             }
             other => panic!("unexpected first meta event: {other:?}"),
         }
@@ -1075,7 +1457,7 @@ mod tests {
         assert_eq!(roundtrip_track1.len(), 4);
         match &roundtrip_track1[0].kind {
             TrackEventKind::Meta(MetaMessage::TrackName(name)) => {
-                assert_eq!(name.as_ref(), b"Lead")
+                assert_eq!(*name, b"Lead")
             }
             other => panic!("unexpected track name event: {other:?}"),
         }
@@ -1112,4 +1494,379 @@ mod tests {
             TrackEventKind::Meta(MetaMessage::EndOfTrack)
         ));
     }
+
+    #[test]
+    fn three_byte_midi_layout_roundtrips_and_migrates() {
+        let channel = u4::from(2);
+        let smf = Smf {
+            header: SmfHeader::new(Format::SingleTrack, Timing::Metrical(u15::from(480))),
+            tracks: vec![vec![
+                TrackEvent {
+                    delta: 0.into(),
+                    kind: TrackEventKind::Midi {
+                        channel,
+                        message: MidiMessage::ProgramChange {
+                            program: u7::from(5),
+                        },
+                    },
+                },
+                TrackEvent {
+                    delta: 10.into(),
+                    kind: TrackEventKind::Midi {
+                        channel,
+                        message: MidiMessage::ChannelAftertouch { vel: u7::from(64) },
+                    },
+                },
+                TrackEvent {
+                    delta: 0.into(),
+                    kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+                },
+            ]],
+        };
+        let mut midi_bytes = Vec::new();
+        smf.write(&mut midi_bytes).expect("writing SMF succeeds");
+
+        let padded = convert_midi_to_tsq_vec(&midi_bytes).expect("conversion succeeds");
+        let options = MidiToTsqOptions {
+            legacy_midi_layout: true,
+            ..Default::default()
+        };
+        let legacy = convert_midi_to_tsq_vec_with_options(&midi_bytes, &options)
+            .expect("conversion succeeds");
+        let flags = u16::from_le_bytes([padded[12], padded[13]]);
+        assert_eq!(flags, FLAG_MIDI_THREE_BYTES);
+        assert_eq!(
+            &padded[22..32],
+            &[0x01, 0x00, 0xC2, 0x05, 0x00, 0x01, 0x0A, 0xD2, 0x40, 0x00]
+        );
+        assert_eq!(padded.len(), legacy.len() + 2);
+
+        for tsq in [&legacy, &padded] {
+            let roundtrip = convert_tsq_to_midi_vec(tsq).expect("conversion succeeds");
+            assert_eq!(Smf::parse(&roundtrip).expect("roundtrip parses"), smf);
+        }
+        for tsq in [&legacy, &padded] {
+            let migrated = migrate_midi_layout(tsq).expect("migration succeeds");
+            assert_eq!(migrated, padded);
+        }
+    }
+
+    #[test]
+    fn osc_and_custom_events_are_dropped_with_delta_carried() {
+        let mut seq = Sequence::new(480);
+        seq.tracks.push(Track::with_events(vec![
+            Event::musical(
+                100,
+                EventKind::Osc(OscEvent::raw(b"/go\0,\0\0\0".to_vec()).unwrap()),
+            ),
+            Event::musical(15, EventKind::Custom(CustomEvent::new(0x42, vec![1, 2]))),
+            Event::musical(5, EventKind::Midi(MidiEvent::new(0x90, 60, 100))),
+            Event::musical(0, EventKind::Meta(MetaEvent::new(0x2F, vec![]))),
+        ]));
+        let mut tsq = seq.to_vec().expect("encoding succeeds");
+        // Corrupt the OSC address; export must not care.
+        let osc = tsq.windows(3).position(|w| w == b"/go").unwrap();
+        tsq[osc] = b'x';
+
+        let smf = super::convert_tsq_to_smf(&tsq, &TsqToMidiOptions::default(), None)
+            .expect("conversion succeeds");
+        let track = &smf.tracks[0];
+        assert_eq!(track.len(), 2);
+        assert_eq!(track[0].delta.as_int(), 120);
+        assert!(matches!(
+            &track[0].kind,
+            TrackEventKind::Midi {
+                message: MidiMessage::NoteOn { .. },
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn nanosecond_abs_unit_converts_musical_events() {
+        let mut seq = Sequence::new(480);
+        seq.header.abs_unit = AbsUnit::Nanoseconds;
+        seq.tracks.push(Track::with_events(vec![
+            Event::musical(0, EventKind::Midi(MidiEvent::new(0x90, 60, 100))),
+            Event::musical(0, EventKind::Meta(MetaEvent::new(0x2F, vec![]))),
+        ]));
+        let tsq = seq.to_vec().expect("encoding succeeds");
+        assert_eq!(tsq[8], 1);
+
+        let smf = super::convert_tsq_to_smf(&tsq, &TsqToMidiOptions::default(), None)
+            .expect("conversion succeeds");
+        assert_eq!(smf.tracks[0].len(), 2);
+
+        seq.tracks[0].events[0].domain = Domain::Absolute;
+        let tsq = seq.to_vec().expect("encoding succeeds");
+        assert!(matches!(
+            super::convert_tsq_to_smf(&tsq, &TsqToMidiOptions::default(), None),
+            Err(Error::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn tempo_map_extraction_roundtrip() {
+        let conductor = vec![
+            TrackEvent {
+                delta: 0.into(),
+                kind: TrackEventKind::Meta(MetaMessage::TimeSignature(4, 2, 24, 8)),
+            },
+            TrackEvent {
+                delta: 0.into(),
+                kind: TrackEventKind::Meta(MetaMessage::Tempo(u24::from(500_000))),
+            },
+            TrackEvent {
+                delta: 0.into(),
+                kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+            },
+        ];
+        let channel = u4::from(0);
+        let key = u7::from(60);
+        let lead = vec![
+            TrackEvent {
+                delta: u28::from(960),
+                kind: TrackEventKind::Meta(MetaMessage::Tempo(u24::from(400_000))),
+            },
+            TrackEvent {
+                delta: 0.into(),
+                kind: TrackEventKind::Midi {
+                    channel,
+                    message: MidiMessage::NoteOn {
+                        key,
+                        vel: 100.into(),
+                    },
+                },
+            },
+            TrackEvent {
+                delta: u28::from(480),
+                kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+            },
+        ];
+        let smf = Smf {
+            header: SmfHeader::new(Format::Parallel, Timing::Metrical(u15::from(480))),
+            tracks: vec![conductor, lead],
+        };
+        let mut midi_bytes = Vec::new();
+        smf.write(&mut midi_bytes).expect("writing SMF succeeds");
+
+        let options = MidiToTsqOptions {
+            extract_tempo_map: true,
+            ..Default::default()
+        };
+        let tsq = convert_midi_to_tsq_vec_with_options(&midi_bytes, &options)
+            .expect("conversion succeeds");
+        let seq = read(&tsq).expect("read succeeds");
+        assert_eq!(
+            seq.chunks,
+            vec![Chunk::TempoMap(TempoMap::from_entries(vec![
+                TempoEntry {
+                    tick: 0,
+                    us_per_qn: 500_000,
+                },
+                TempoEntry {
+                    tick: 960,
+                    us_per_qn: 400_000,
+                },
+            ]))]
+        );
+        let is_tempo =
+            |event: &Event| matches!(&event.kind, EventKind::Meta(meta) if meta.meta_type == 0x51);
+        let mut events = seq.tracks.iter().flat_map(|track| &track.events);
+        assert!(!events.any(is_tempo));
+        // The note keeps its position once the tempo event is removed.
+        assert_eq!(seq.tracks[1].events[0].delta, 960);
+
+        let smf = super::convert_tsq_to_smf(&tsq, &TsqToMidiOptions::default(), None)
+            .expect("conversion succeeds");
+        let conductor = &smf.tracks[0];
+        assert_eq!(conductor.len(), 4);
+        assert!(matches!(
+            conductor[0].kind,
+            TrackEventKind::Meta(MetaMessage::TimeSignature(..))
+        ));
+        match (&conductor[1], &conductor[2]) {
+            (
+                TrackEvent {
+                    delta: d0,
+                    kind: TrackEventKind::Meta(MetaMessage::Tempo(t0)),
+                },
+                TrackEvent {
+                    delta: d1,
+                    kind: TrackEventKind::Meta(MetaMessage::Tempo(t1)),
+                },
+            ) => {
+                assert_eq!((d0.as_int(), t0.as_int()), (0, 500_000));
+                assert_eq!((d1.as_int(), t1.as_int()), (960, 400_000));
+            }
+            other => panic!("expected tempo events, got {other:?}"),
+        }
+        assert!(matches!(
+            conductor[3],
+            TrackEvent {
+                kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+                ..
+            }
+        ));
+        assert_eq!(smf.tracks[1].len(), 2);
+    }
+
+    #[test]
+    fn timecode_smf_roundtrips_through_absolute_events() {
+        let channel = u4::from(0);
+        let note = |delta: u32, vel: u8| TrackEvent {
+            delta: u28::from(delta),
+            kind: TrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOn {
+                    key: u7::from(60),
+                    vel: u7::from(vel),
+                },
+            },
+        };
+        let smf = Smf {
+            header: SmfHeader::new(Format::SingleTrack, Timing::Timecode(Fps::Fps29, 80)),
+            tracks: vec![vec![
+                note(0, 100),
+                note(1, 0),
+                note(2, 100),
+                note(997, 0),
+                TrackEvent {
+                    delta: 0.into(),
+                    kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+                },
+            ]],
+        };
+        let mut midi_bytes = Vec::new();
+        smf.write(&mut midi_bytes).expect("writing SMF succeeds");
+
+        let options = MidiToTsqOptions {
+            abs_unit: AbsUnit::Nanoseconds,
+            ..Default::default()
+        };
+        let tsq = convert_midi_to_tsq_vec_with_options(&midi_bytes, &options)
+            .expect("conversion succeeds");
+        let seq = read(&tsq).expect("read succeeds");
+        assert_eq!(seq.header.abs_unit, AbsUnit::Nanoseconds);
+        assert_eq!(seq.header.ppq, 480);
+        let times: Vec<EventTime> = seq.tracks[0].timed_events().map(|(time, _)| time).collect();
+        // One tick of 29.97 fps at 80 ticks per frame lasts 417_083.3 ns.
+        let expected = [0, 417_083, 1_251_250, 417_083_333, 417_083_333];
+        assert_eq!(times, expected.map(EventTime::Absolute));
+
+        let timecode = TsqToMidiOptions {
+            timecode: Some(SmpteTiming::new(SmpteFps::Fps29_97Drop, 80)),
+        };
+        let roundtrip =
+            convert_tsq_to_midi_vec_with_options(&tsq, &timecode).expect("conversion succeeds");
+        assert_eq!(roundtrip, midi_bytes);
+
+        assert!(matches!(
+            convert_tsq_to_midi_vec(&tsq),
+            Err(Error::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn sequential_smf_format_is_preserved() {
+        let pattern = |us_per_qn: u32, key: u8| {
+            vec![
+                TrackEvent {
+                    delta: 0.into(),
+                    kind: TrackEventKind::Meta(MetaMessage::Tempo(u24::from(us_per_qn))),
+                },
+                TrackEvent {
+                    delta: u28::from(240),
+                    kind: TrackEventKind::Midi {
+                        channel: u4::from(0),
+                        message: MidiMessage::NoteOn {
+                            key: u7::from(key),
+                            vel: u7::from(100),
+                        },
+                    },
+                },
+                TrackEvent {
+                    delta: 0.into(),
+                    kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+                },
+            ]
+        };
+        let smf = Smf {
+            header: SmfHeader::new(Format::Sequential, Timing::Metrical(u15::from(480))),
+            tracks: vec![pattern(500_000, 60), pattern(400_000, 64)],
+        };
+        let mut midi_bytes = Vec::new();
+        smf.write(&mut midi_bytes).expect("writing SMF succeeds");
+
+        // Each pattern keeps its own tempo even when extraction is requested.
+        let options = MidiToTsqOptions {
+            extract_tempo_map: true,
+            ..Default::default()
+        };
+        let tsq = convert_midi_to_tsq_vec_with_options(&midi_bytes, &options)
+            .expect("conversion succeeds");
+        let mut seq = read(&tsq).expect("read succeeds");
+        assert!(seq.header.sequential_tracks());
+        assert!(seq.chunks.is_empty());
+        assert_eq!(seq.patterns().len(), 2);
+
+        let roundtrip = convert_tsq_to_midi_vec(&tsq).expect("conversion succeeds");
+        assert_eq!(roundtrip, midi_bytes);
+
+        // A tempo map applies to every pattern's own timeline.
+        for track in &mut seq.tracks {
+            track.events.remove(0);
+            track.events[0].delta = 240;
+        }
+        let tempo = TempoEntry {
+            tick: 0,
+            us_per_qn: 600_000,
+        };
+        seq.chunks
+            .push(Chunk::TempoMap(TempoMap::from_entries(vec![tempo])));
+        let tsq = seq.to_vec().expect("encoding succeeds");
+        let smf = super::convert_tsq_to_smf(&tsq, &TsqToMidiOptions::default(), None)
+            .expect("conversion succeeds");
+        assert_eq!(smf.header.format, Format::Sequential);
+        for track in &smf.tracks {
+            assert_eq!(
+                track[0].kind,
+                TrackEventKind::Meta(MetaMessage::Tempo(u24::from(600_000)))
+            );
+        }
+    }
+
+    #[test]
+    fn recovers_midi_from_truncated_recording() {
+        let mut seq = Sequence::new(480);
+        for key in [60, 64] {
+            seq.tracks.push(Track::with_events(vec![
+                Event::musical(0, EventKind::Midi(MidiEvent::new(0x90, key, 100))),
+                Event::musical(480, EventKind::Midi(MidiEvent::new(0x80, key, 0))),
+                Event::musical(0, EventKind::Meta(MetaEvent::new(0x01, vec![b'a'; 8]))),
+            ]));
+        }
+        let mut tsq = seq.to_vec().expect("encoding succeeds");
+        tsq.truncate(tsq.len() - 4);
+        assert!(convert_tsq_to_midi_vec(&tsq).is_err());
+
+        let recovered =
+            recover_tsq_to_midi_vec(&tsq, &TsqToMidiOptions::default()).expect("recovery succeeds");
+        let smf = Smf::parse(&recovered.value).expect("recovered MIDI parses");
+        assert_eq!(smf.tracks.len(), 2);
+        assert_eq!(smf.tracks[0].len(), 3);
+        assert_eq!(smf.tracks[1].len(), 2);
+        let tracks: Vec<Option<usize>> = recovered
+            .diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.track)
+            .collect();
+        assert_eq!(tracks, [Some(1), Some(1)]);
+
+        let intact = seq.to_vec().expect("encoding succeeds");
+        let recovered = recover_tsq_to_midi_vec(&intact, &TsqToMidiOptions::default())
+            .expect("recovery succeeds");
+        assert!(recovered.diagnostics.is_empty());
+        assert_eq!(recovered.value, convert_tsq_to_midi_vec(&intact).unwrap());
+    }
 }
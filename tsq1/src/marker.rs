@@ -0,0 +1,158 @@
+//! `"MARK"` locators (spec §6).
+
+use alloc::string::String;
+
+use crate::event::{Domain, EventTime};
+use crate::sequence::domain_index;
+
+/// Locator classification (`class`).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum LocatorClass {
+    /// General-purpose locator (`0x00`).
+    #[default]
+    Generic,
+    /// Cue point (`0x20`).
+    Cue,
+    /// Application-defined locator (`0x7F`).
+    Custom,
+}
+
+impl LocatorClass {
+    /// Interpret a `class` byte; values the spec does not define are treated
+    /// as [`LocatorClass::Generic`].
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            0x20 => LocatorClass::Cue,
+            0x7F => LocatorClass::Custom,
+            _ => LocatorClass::Generic,
+        }
+    }
+
+    /// The encoded `class` byte.
+    pub fn as_u8(self) -> u8 {
+        match self {
+            LocatorClass::Generic => 0x00,
+            LocatorClass::Cue => 0x20,
+            LocatorClass::Custom => 0x7F,
+        }
+    }
+}
+
+/// One locator entry of a `"MARK"` chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locator {
+    /// Axis of `pos` (`pos_kind` `0` = musical, `1` = absolute).
+    pub pos_kind: Domain,
+    /// Position in ticks or `AbsUnit`, depending on `pos_kind`.
+    pub pos: u64,
+    pub name: String,
+    /// `class` byte exactly as stored; see [`Locator::class_kind`].
+    pub class: u8,
+    /// Optional `0xAARRGGBB` color; `None` when the entry has no color field.
+    pub color: Option<u32>,
+}
+
+impl Locator {
+    /// Generic locator without a color.
+    pub fn new(position: EventTime, name: impl Into<String>) -> Self {
+        Locator {
+            pos_kind: position.domain(),
+            pos: position.value(),
+            name: name.into(),
+            class: LocatorClass::Generic.as_u8(),
+            color: None,
+        }
+    }
+
+    pub fn with_class(mut self, class: LocatorClass) -> Self {
+        self.class = class.as_u8();
+        self
+    }
+
+    pub fn with_color(mut self, color: u32) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    /// Position of the locator on its own axis.
+    pub fn position(&self) -> EventTime {
+        match self.pos_kind {
+            Domain::Musical => EventTime::Musical(self.pos),
+            Domain::Absolute => EventTime::Absolute(self.pos),
+        }
+    }
+
+    /// Classification of the locator, with unknown classes as `Generic`.
+    pub fn class_kind(&self) -> LocatorClass {
+        LocatorClass::from_u8(self.class)
+    }
+
+    /// Whether the entry carries the optional `color_rgba` field.
+    pub fn has_color(&self) -> bool {
+        self.color.is_some()
+    }
+}
+
+/// Whether locators are sorted by `pos` within each `pos_kind`, as the spec
+/// requires. Musical and absolute locators may be interleaved.
+pub fn locators_sorted(locators: &[Locator]) -> bool {
+    let mut last: [Option<u64>; 2] = [None; 2];
+    locators.iter().all(|locator| {
+        let slot = &mut last[domain_index(locator.pos_kind)];
+        let sorted = slot.is_none_or(|pos| pos <= locator.pos);
+        *slot = Some(locator.pos);
+        sorted
+    })
+}
+
+/// Sort locators musical first, then absolute, each by `pos`. The sort is
+/// stable, so locators sharing a position keep their order.
+pub fn sort_locators(locators: &mut [Locator]) {
+    locators.sort_by_key(|locator| (domain_index(locator.pos_kind), locator.pos));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn unknown_classes_are_generic() {
+        let locator = Locator::new(EventTime::Musical(0), "Verse");
+        assert_eq!(locator.class_kind(), LocatorClass::Generic);
+        for (value, class) in [
+            (0x00, LocatorClass::Generic),
+            (0x20, LocatorClass::Cue),
+            (0x7F, LocatorClass::Custom),
+            (0x55, LocatorClass::Generic),
+        ] {
+            assert_eq!(LocatorClass::from_u8(value), class);
+        }
+        let unknown = Locator {
+            class: 0x55,
+            ..locator.with_color(0xFF00FF00)
+        };
+        assert_eq!(unknown.class_kind(), LocatorClass::Generic);
+        assert!(unknown.has_color());
+    }
+
+    #[test]
+    fn sort_order_is_per_pos_kind() {
+        let mut locators = vec![
+            Locator::new(EventTime::Absolute(5_000), "b"),
+            Locator::new(EventTime::Musical(960), "a"),
+            Locator::new(EventTime::Absolute(1_000), "c"),
+            Locator::new(EventTime::Musical(960), "d"),
+        ];
+        assert!(!locators_sorted(&locators));
+        sort_locators(&mut locators);
+        assert!(locators_sorted(&locators));
+        let names: Vec<&str> = locators.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["a", "d", "c", "b"]);
+
+        // Interleaving kinds is fine as long as each kind is sorted.
+        locators.swap(1, 2);
+        assert!(locators_sorted(&locators));
+    }
+}
@@ -0,0 +1,602 @@
+//! OSC event payloads (spec §4.1) and a typed OSC 1.0/1.1 codec.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::event::{OscEvent, OscFormat};
+use crate::Error;
+
+#[cfg(feature = "cbor")]
+mod cbor;
+#[cfg(feature = "msgpack")]
+mod msgpack;
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+mod value;
+
+const BUNDLE_TAG: &[u8; 8] = b"#bundle\0";
+/// Maximum nesting of bundles and arrays accepted by the decoder.
+const MAX_DEPTH: usize = 64;
+
+/// Check a RAW OSC datagram against the spec §4.1 validation rules.
+///
+/// The datagram must start with `'/'` (message) or `'#'` (bundle), keep OSC's
+/// 4-byte alignment and decode as a well-formed [`OscPacket`].
+pub fn validate_raw(data: &[u8]) -> Result<(), Error> {
+    match data.first() {
+        Some(b'/') | Some(b'#') => {}
+        Some(_) => return Err(Error::Invalid("OSC RAW payload must start with '/' or '#'")),
+        None => return Err(Error::Invalid("OSC RAW payload is empty")),
+    }
+    if !data.len().is_multiple_of(4) {
+        return Err(Error::Invalid("OSC RAW payload is not 4-byte aligned"));
+    }
+    OscPacket::decode(data).map(|_| ())
+}
+
+/// A single OSC argument, keyed by its type tag.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscArg {
+    /// `i`: 32-bit integer.
+    Int(i32),
+    /// `f`: 32-bit float.
+    Float(f32),
+    /// `s`: string.
+    String(String),
+    /// `b`: blob.
+    Blob(Vec<u8>),
+    /// `h`: 64-bit integer.
+    Long(i64),
+    /// `d`: 64-bit float.
+    Double(f64),
+    /// `t`: NTP timetag.
+    Timetag(u64),
+    /// `S`: symbol (alternate string).
+    Symbol(String),
+    /// `c`: ASCII character sent as 32 bits.
+    Char(char),
+    /// `r`: 32-bit RGBA color.
+    Rgba(u32),
+    /// `m`: 4-byte MIDI message (port, status, data1, data2).
+    Midi([u8; 4]),
+    /// `T`
+    True,
+    /// `F`
+    False,
+    /// `N`
+    Nil,
+    /// `I`: impulse (infinitum).
+    Impulse,
+    /// `[` ... `]`: nested array.
+    Array(Vec<OscArg>),
+}
+
+impl OscArg {
+    fn push_tag(&self, tags: &mut Vec<u8>) {
+        let tag = match self {
+            OscArg::Int(_) => b'i',
+            OscArg::Float(_) => b'f',
+            OscArg::String(_) => b's',
+            OscArg::Blob(_) => b'b',
+            OscArg::Long(_) => b'h',
+            OscArg::Double(_) => b'd',
+            OscArg::Timetag(_) => b't',
+            OscArg::Symbol(_) => b'S',
+            OscArg::Char(_) => b'c',
+            OscArg::Rgba(_) => b'r',
+            OscArg::Midi(_) => b'm',
+            OscArg::True => b'T',
+            OscArg::False => b'F',
+            OscArg::Nil => b'N',
+            OscArg::Impulse => b'I',
+            OscArg::Array(items) => {
+                tags.push(b'[');
+                for item in items {
+                    item.push_tag(tags);
+                }
+                b']'
+            }
+        };
+        tags.push(tag);
+    }
+
+    fn encode_data(&self, out: &mut Vec<u8>) -> Result<(), Error> {
+        match self {
+            OscArg::Int(v) => out.extend_from_slice(&v.to_be_bytes()),
+            OscArg::Float(v) => out.extend_from_slice(&v.to_bits().to_be_bytes()),
+            OscArg::String(v) | OscArg::Symbol(v) => write_padded_str(v.as_bytes(), out)?,
+            OscArg::Blob(v) => {
+                let len = i32::try_from(v.len())
+                    .map_err(|_| Error::DataOverflow("OSC blob too large"))?;
+                out.extend_from_slice(&len.to_be_bytes());
+                out.extend_from_slice(v);
+                pad_to_four(out);
+            }
+            OscArg::Long(v) => out.extend_from_slice(&v.to_be_bytes()),
+            OscArg::Double(v) => out.extend_from_slice(&v.to_bits().to_be_bytes()),
+            OscArg::Timetag(v) => out.extend_from_slice(&v.to_be_bytes()),
+            OscArg::Char(c) => {
+                if !c.is_ascii() {
+                    return Err(Error::Invalid("OSC char argument must be ASCII"));
+                }
+                out.extend_from_slice(&(*c as u32).to_be_bytes());
+            }
+            OscArg::Rgba(v) => out.extend_from_slice(&v.to_be_bytes()),
+            OscArg::Midi(bytes) => out.extend_from_slice(bytes),
+            OscArg::True | OscArg::False | OscArg::Nil | OscArg::Impulse => {}
+            OscArg::Array(items) => {
+                for item in items {
+                    item.encode_data(out)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An OSC message: address pattern plus typed arguments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub args: Vec<OscArg>,
+}
+
+impl OscMessage {
+    pub fn new(address: impl Into<String>, args: Vec<OscArg>) -> Self {
+        OscMessage {
+            address: address.into(),
+            args,
+        }
+    }
+
+    /// The type tag string including the leading `','`.
+    pub fn type_tags(&self) -> String {
+        let mut tags = Vec::with_capacity(self.args.len() + 1);
+        tags.push(b',');
+        for arg in &self.args {
+            arg.push_tag(&mut tags);
+        }
+        // Tags are always ASCII.
+        tags.into_iter().map(char::from).collect()
+    }
+
+    fn encode_into(&self, out: &mut Vec<u8>) -> Result<(), Error> {
+        if !self.address.starts_with('/') {
+            return Err(Error::Invalid("OSC address must start with '/'"));
+        }
+        write_padded_str(self.address.as_bytes(), out)?;
+        write_padded_str(self.type_tags().as_bytes(), out)?;
+        for arg in &self.args {
+            arg.encode_data(out)?;
+        }
+        Ok(())
+    }
+}
+
+/// An OSC bundle: timetag plus nested messages and bundles.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscBundle {
+    /// NTP timetag; `1` means "immediately".
+    pub timetag: u64,
+    pub elements: Vec<OscPacket>,
+}
+
+impl OscBundle {
+    pub fn new(timetag: u64, elements: Vec<OscPacket>) -> Self {
+        OscBundle { timetag, elements }
+    }
+
+    fn encode_into(&self, out: &mut Vec<u8>) -> Result<(), Error> {
+        out.extend_from_slice(BUNDLE_TAG);
+        out.extend_from_slice(&self.timetag.to_be_bytes());
+        for element in &self.elements {
+            let size_pos = out.len();
+            out.extend_from_slice(&[0; 4]);
+            element.encode_into(out)?;
+            let size = i32::try_from(out.len() - size_pos - 4)
+                .map_err(|_| Error::DataOverflow("OSC bundle element too large"))?;
+            out[size_pos..size_pos + 4].copy_from_slice(&size.to_be_bytes());
+        }
+        Ok(())
+    }
+}
+
+/// Either an OSC message or an OSC bundle.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscPacket {
+    Message(OscMessage),
+    Bundle(OscBundle),
+}
+
+impl From<OscMessage> for OscPacket {
+    fn from(message: OscMessage) -> Self {
+        OscPacket::Message(message)
+    }
+}
+
+impl From<OscBundle> for OscPacket {
+    fn from(bundle: OscBundle) -> Self {
+        OscPacket::Bundle(bundle)
+    }
+}
+
+impl OscPacket {
+    /// Decode a binary OSC datagram.
+    pub fn decode(data: &[u8]) -> Result<Self, Error> {
+        let mut cursor = data;
+        let packet = decode_packet(&mut cursor, 0)?;
+        if !cursor.is_empty() {
+            return Err(Error::Invalid("trailing bytes after OSC packet"));
+        }
+        Ok(packet)
+    }
+
+    /// Encode as a binary OSC datagram.
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        let mut out = Vec::new();
+        self.encode_into(&mut out)?;
+        Ok(out)
+    }
+
+    fn encode_into(&self, out: &mut Vec<u8>) -> Result<(), Error> {
+        match self {
+            OscPacket::Message(message) => message.encode_into(out),
+            OscPacket::Bundle(bundle) => bundle.encode_into(out),
+        }
+    }
+}
+
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+    if data.len() < len {
+        return Err(Error::Invalid("OSC datagram truncated"));
+    }
+    let (prefix, rest) = data.split_at(len);
+    *data = rest;
+    Ok(prefix)
+}
+
+fn take_array<const N: usize>(data: &mut &[u8]) -> Result<[u8; N], Error> {
+    let mut buf = [0u8; N];
+    buf.copy_from_slice(take(data, N)?);
+    Ok(buf)
+}
+
+/// Read a NUL-terminated string padded with NULs to a multiple of four bytes.
+fn read_padded_str<'a>(data: &mut &'a [u8]) -> Result<&'a str, Error> {
+    let end = data
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or(Error::Invalid("OSC string missing terminator"))?;
+    let padded = (end + 4) & !3;
+    let bytes = take(data, padded)?;
+    if bytes[end..].iter().any(|&b| b != 0) {
+        return Err(Error::Invalid("OSC string padding must be zero"));
+    }
+    core::str::from_utf8(&bytes[..end]).map_err(|_| Error::Invalid("OSC string is not UTF-8"))
+}
+
+fn write_padded_str(bytes: &[u8], out: &mut Vec<u8>) -> Result<(), Error> {
+    if bytes.contains(&0) {
+        return Err(Error::Invalid("OSC string must not contain NUL"));
+    }
+    out.extend_from_slice(bytes);
+    out.push(0);
+    pad_to_four(out);
+    Ok(())
+}
+
+fn pad_to_four(out: &mut Vec<u8>) {
+    while !out.len().is_multiple_of(4) {
+        out.push(0);
+    }
+}
+
+fn decode_packet(data: &mut &[u8], depth: usize) -> Result<OscPacket, Error> {
+    if depth > MAX_DEPTH {
+        return Err(Error::Invalid("OSC packet nested too deeply"));
+    }
+    match data.first() {
+        Some(b'/') => decode_message(data).map(OscPacket::Message),
+        Some(b'#') => decode_bundle(data, depth).map(OscPacket::Bundle),
+        _ => Err(Error::Invalid("OSC packet must start with '/' or '#'")),
+    }
+}
+
+fn decode_message(data: &mut &[u8]) -> Result<OscMessage, Error> {
+    let address = String::from(read_padded_str(data)?);
+    // OSC 1.0 asks decoders to accept messages from older senders that omit
+    // the type tag string; such a message has no arguments.
+    if data.first() != Some(&b',') {
+        return Ok(OscMessage {
+            address,
+            args: Vec::new(),
+        });
+    }
+    let mut tags = &read_padded_str(data)?.as_bytes()[1..];
+    let args = decode_args(&mut tags, data, 0)?;
+    Ok(OscMessage { address, args })
+}
+
+/// Decode arguments until the tags run out, or until the `]` closing the
+/// array at nesting level `depth` (0 = top level).
+fn decode_args(tags: &mut &[u8], data: &mut &[u8], depth: usize) -> Result<Vec<OscArg>, Error> {
+    if depth > MAX_DEPTH {
+        return Err(Error::Invalid("OSC array nested too deeply"));
+    }
+    let mut args = Vec::new();
+    while let Some((&tag, rest)) = tags.split_first() {
+        *tags = rest;
+        let arg = match tag {
+            b'i' => OscArg::Int(i32::from_be_bytes(take_array(data)?)),
+            b'f' => OscArg::Float(f32::from_bits(u32::from_be_bytes(take_array(data)?))),
+            b's' => OscArg::String(String::from(read_padded_str(data)?)),
+            b'S' => OscArg::Symbol(String::from(read_padded_str(data)?)),
+            b'b' => {
+                let len = i32::from_be_bytes(take_array(data)?);
+                let len = usize::try_from(len)
+                    .map_err(|_| Error::Invalid("OSC blob length is negative"))?;
+                let padded = len
+                    .checked_add(3)
+                    .ok_or(Error::Invalid("OSC blob length overflow"))?
+                    & !3;
+                let bytes = take(data, padded)?;
+                if bytes[len..].iter().any(|&b| b != 0) {
+                    return Err(Error::Invalid("OSC blob padding must be zero"));
+                }
+                OscArg::Blob(bytes[..len].to_vec())
+            }
+            b'h' => OscArg::Long(i64::from_be_bytes(take_array(data)?)),
+            b'd' => OscArg::Double(f64::from_bits(u64::from_be_bytes(take_array(data)?))),
+            b't' => OscArg::Timetag(u64::from_be_bytes(take_array(data)?)),
+            b'c' => {
+                let value = u32::from_be_bytes(take_array(data)?);
+                let c = char::from_u32(value)
+                    .filter(char::is_ascii)
+                    .ok_or(Error::Invalid("OSC char argument must be ASCII"))?;
+                OscArg::Char(c)
+            }
+            b'r' => OscArg::Rgba(u32::from_be_bytes(take_array(data)?)),
+            b'm' => OscArg::Midi(take_array(data)?),
+            b'T' => OscArg::True,
+            b'F' => OscArg::False,
+            b'N' => OscArg::Nil,
+            b'I' => OscArg::Impulse,
+            b'[' => OscArg::Array(decode_args(tags, data, depth + 1)?),
+            b']' if depth > 0 => return Ok(args),
+            _ => return Err(Error::Invalid("unsupported OSC type tag")),
+        };
+        args.push(arg);
+    }
+    if depth > 0 {
+        return Err(Error::Invalid("unterminated OSC array"));
+    }
+    Ok(args)
+}
+
+fn decode_bundle(data: &mut &[u8], depth: usize) -> Result<OscBundle, Error> {
+    if take(data, 8)? != BUNDLE_TAG {
+        return Err(Error::Invalid("OSC bundle must start with \"#bundle\""));
+    }
+    let timetag = u64::from_be_bytes(take_array(data)?);
+    let mut elements = Vec::new();
+    while !data.is_empty() {
+        let size = i32::from_be_bytes(take_array(data)?);
+        let size = usize::try_from(size)
+            .ok()
+            .filter(|size| size.is_multiple_of(4))
+            .ok_or(Error::Invalid("invalid OSC bundle element size"))?;
+        let mut element = take(data, size)?;
+        elements.push(decode_packet(&mut element, depth + 1)?);
+        if !element.is_empty() {
+            return Err(Error::Invalid("OSC bundle element size mismatch"));
+        }
+    }
+    Ok(OscBundle { timetag, elements })
+}
+
+impl OscEvent {
+    /// Wrap a RAW OSC datagram, validating it first.
+    pub fn raw(data: Vec<u8>) -> Result<Self, Error> {
+        validate_raw(&data)?;
+        Ok(OscEvent {
+            format: OscFormat::Raw,
+            data,
+        })
+    }
+
+    /// Encode a typed packet as a RAW OSC event.
+    pub fn from_packet(packet: &OscPacket) -> Result<Self, Error> {
+        Self::encode_packet(packet, OscFormat::Raw)
+    }
+
+    /// Encode a typed packet using the given payload format.
+    ///
+    /// MSGPACK and CBOR require the `msgpack` and `cbor` features.
+    pub fn encode_packet(packet: &OscPacket, format: OscFormat) -> Result<Self, Error> {
+        let data = match format {
+            OscFormat::Raw => packet.encode()?,
+            #[cfg(feature = "msgpack")]
+            OscFormat::MsgPack => packet.to_msgpack()?,
+            #[cfg(feature = "cbor")]
+            OscFormat::Cbor => packet.to_cbor()?,
+            _ => return Err(Error::Unsupported("OSC payload format")),
+        };
+        Ok(OscEvent { format, data })
+    }
+
+    /// Decode the payload into a typed packet.
+    pub fn packet(&self) -> Result<OscPacket, Error> {
+        match self.format {
+            OscFormat::Raw => OscPacket::decode(&self.data),
+            #[cfg(feature = "msgpack")]
+            OscFormat::MsgPack => OscPacket::from_msgpack(&self.data),
+            #[cfg(feature = "cbor")]
+            OscFormat::Cbor => OscPacket::from_cbor(&self.data),
+            _ => Err(Error::Unsupported("OSC payload format")),
+        }
+    }
+
+    /// Re-encode the payload in another format.
+    ///
+    /// Conversion goes through [`OscPacket`] and is lossless between RAW,
+    /// MSGPACK and CBOR.
+    pub fn to_format(&self, format: OscFormat) -> Result<Self, Error> {
+        if format == self.format {
+            return Ok(self.clone());
+        }
+        Self::encode_packet(&self.packet()?, format)
+    }
+
+    /// Validate the payload according to its format.
+    ///
+    /// Only RAW payloads are checked; other formats are accepted as-is.
+    pub fn validate(&self) -> Result<(), Error> {
+        match self.format {
+            OscFormat::Raw => validate_raw(&self.data),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn accepts_aligned_messages_and_bundles() {
+        assert!(validate_raw(b"/light/flash\0\0\0\0,i\0\0\0\0\0\x01").is_ok());
+        assert!(validate_raw(b"#bundle\0\0\0\0\0\0\0\0\x01").is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_raw_payloads() {
+        assert!(validate_raw(b"").is_err());
+        assert!(validate_raw(b"xyz\0").is_err());
+        assert!(validate_raw(b"/ab").is_err());
+        assert!(OscEvent::raw(b"/a\0\0\0".to_vec()).is_err());
+        assert!(validate_raw(b"/a\0\0,x\0\0").is_err());
+        assert!(validate_raw(b"/a\0\0,i\0\0").is_err());
+        // Untagged messages have no arguments to carry data.
+        assert!(validate_raw(b"/a\0\0\0\0\0\x01").is_err());
+        // The element size runs past the end of the bundle.
+        assert!(validate_raw(b"#bundle\0\0\0\0\0\0\0\0\x01\0\0\0\x08/a\0\0").is_err());
+    }
+
+    #[test]
+    fn accepts_messages_without_type_tags() {
+        let untagged = OscPacket::Message(OscMessage::new("/a", vec![]));
+        assert_eq!(OscPacket::decode(b"/a\0\0").unwrap(), untagged);
+        assert!(validate_raw(b"/a\0\0").is_ok());
+        assert_eq!(
+            OscPacket::decode(b"#bundle\0\0\0\0\0\0\0\0\x01\0\0\0\x04/a\0\0").unwrap(),
+            OscPacket::Bundle(OscBundle::new(1, vec![untagged]))
+        );
+    }
+
+    #[test]
+    fn rejects_excessive_array_nesting() {
+        let mut data = b"/deep\0\0\0".to_vec();
+        data.push(b',');
+        data.extend(core::iter::repeat_n(b'[', 100));
+        data.extend(core::iter::repeat_n(b']', 100));
+        data.push(0);
+        while !data.len().is_multiple_of(4) {
+            data.push(0);
+        }
+        assert!(OscPacket::decode(&data).is_err());
+    }
+
+    #[test]
+    fn decodes_spec_example() {
+        let packet = OscPacket::decode(b"/light/flash\0\0\0\0,i\0\0\0\0\0\x01").unwrap();
+        assert_eq!(
+            packet,
+            OscPacket::Message(OscMessage::new("/light/flash", vec![OscArg::Int(1)]))
+        );
+    }
+
+    #[test]
+    fn message_with_every_type_roundtrips() {
+        let message = OscMessage::new(
+            "/all/types",
+            vec![
+                OscArg::Int(-7),
+                OscArg::Float(f32::from_bits(0x7FC0_0001)),
+                OscArg::String(String::from("abc")),
+                OscArg::Blob(vec![1, 2, 3, 4, 5]),
+                OscArg::Long(i64::MIN),
+                OscArg::Double(0.25),
+                OscArg::Timetag(1),
+                OscArg::Symbol(String::from("sym")),
+                OscArg::Char('x'),
+                OscArg::Rgba(0xFF00FF80),
+                OscArg::Midi([0, 0x90, 60, 100]),
+                OscArg::True,
+                OscArg::False,
+                OscArg::Nil,
+                OscArg::Impulse,
+                OscArg::Array(vec![OscArg::Int(1), OscArg::Array(vec![OscArg::True])]),
+            ],
+        );
+        assert_eq!(message.type_tags(), ",ifsbhdtScrmTFNI[i[T]]");
+
+        let bytes = OscPacket::from(message.clone()).encode().unwrap();
+        assert!(bytes.len().is_multiple_of(4));
+        match OscPacket::decode(&bytes).unwrap() {
+            OscPacket::Message(decoded) => {
+                assert_eq!(decoded.address, message.address);
+                assert_eq!(decoded.args.len(), message.args.len());
+                match decoded.args[1] {
+                    OscArg::Float(f) => assert_eq!(f.to_bits(), 0x7FC0_0001),
+                    ref other => panic!("expected float, got {other:?}"),
+                }
+                assert_eq!(decoded.args[2..], message.args[2..]);
+            }
+            other => panic!("expected message, got {other:?}"),
+        }
+    }
+
+    #[cfg(all(feature = "msgpack", feature = "cbor"))]
+    #[test]
+    fn formats_convert_losslessly() {
+        let bundle = OscBundle::new(
+            42,
+            vec![
+                OscMessage::new(
+                    "/mix",
+                    vec![
+                        OscArg::Float(0.5),
+                        OscArg::Double(-1.25),
+                        OscArg::Char('q'),
+                        OscArg::Midi([1, 0xB0, 7, 127]),
+                        OscArg::Impulse,
+                        OscArg::Array(vec![OscArg::String(String::from("x")), OscArg::False]),
+                        OscArg::Blob(vec![]),
+                    ],
+                )
+                .into(),
+                OscBundle::new(1, vec![]).into(),
+            ],
+        );
+        let raw = OscEvent::from_packet(&bundle.into()).unwrap();
+        let msgpack = raw.to_format(OscFormat::MsgPack).unwrap();
+        let cbor = msgpack.to_format(OscFormat::Cbor).unwrap();
+        assert_eq!(cbor.to_format(OscFormat::Raw).unwrap(), raw);
+        assert_eq!(cbor.to_format(OscFormat::MsgPack).unwrap(), msgpack);
+        assert_eq!(raw.to_format(OscFormat::Cbor).unwrap(), cbor);
+    }
+
+    #[test]
+    fn nested_bundle_roundtrips() {
+        let bundle = OscBundle::new(
+            0x0123_4567_89AB_CDEF,
+            vec![
+                OscMessage::new("/a", vec![]).into(),
+                OscBundle::new(1, vec![OscMessage::new("/b", vec![OscArg::Nil]).into()]).into(),
+            ],
+        );
+        let event = OscEvent::from_packet(&bundle.clone().into()).unwrap();
+        assert!(event.validate().is_ok());
+        assert_eq!(event.packet().unwrap(), OscPacket::Bundle(bundle));
+    }
+}
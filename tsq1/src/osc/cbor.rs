@@ -0,0 +1,223 @@
+//! CBOR encoding of the OSC schema (`OscFormat = 0x02`).
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::value::{packet_to_value, value_to_packet, Value, MAX_VALUE_DEPTH};
+use super::OscPacket;
+use crate::Error;
+
+const MAJOR_UINT: u8 = 0;
+const MAJOR_NINT: u8 = 1;
+const MAJOR_BYTES: u8 = 2;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+const MAJOR_SIMPLE: u8 = 7;
+
+impl OscPacket {
+    /// Encode using the CBOR schema.
+    pub fn to_cbor(&self) -> Result<Vec<u8>, Error> {
+        let mut out = Vec::new();
+        encode_value(&packet_to_value(self), &mut out)?;
+        Ok(out)
+    }
+
+    /// Decode a CBOR schema payload.
+    pub fn from_cbor(data: &[u8]) -> Result<Self, Error> {
+        let mut cursor = data;
+        let value = decode_value(&mut cursor, 0)?;
+        if !cursor.is_empty() {
+            return Err(Error::Invalid("trailing bytes after CBOR item"));
+        }
+        value_to_packet(value)
+    }
+}
+
+/// Write an item head using the shortest argument encoding.
+fn encode_head(major: u8, arg: u64, out: &mut Vec<u8>) {
+    let major = major << 5;
+    if arg < 24 {
+        out.push(major | arg as u8);
+    } else if let Ok(v) = u8::try_from(arg) {
+        out.push(major | 24);
+        out.push(v);
+    } else if let Ok(v) = u16::try_from(arg) {
+        out.push(major | 25);
+        out.extend_from_slice(&v.to_be_bytes());
+    } else if let Ok(v) = u32::try_from(arg) {
+        out.push(major | 26);
+        out.extend_from_slice(&v.to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend_from_slice(&arg.to_be_bytes());
+    }
+}
+
+fn encode_value(value: &Value, out: &mut Vec<u8>) -> Result<(), Error> {
+    match value {
+        Value::Nil => out.push(0xF6),
+        Value::Bool(false) => out.push(0xF4),
+        Value::Bool(true) => out.push(0xF5),
+        Value::Int(v) => {
+            let (major, arg) = if *v >= 0 {
+                (MAJOR_UINT, u64::try_from(*v))
+            } else {
+                (MAJOR_NINT, u64::try_from(-1 - *v))
+            };
+            let arg = arg.map_err(|_| Error::DataOverflow("integer exceeds CBOR range"))?;
+            encode_head(major, arg, out);
+        }
+        Value::F32(v) => {
+            out.push(0xFA);
+            out.extend_from_slice(&v.to_bits().to_be_bytes());
+        }
+        Value::F64(v) => {
+            out.push(0xFB);
+            out.extend_from_slice(&v.to_bits().to_be_bytes());
+        }
+        Value::Str(v) => {
+            encode_head(MAJOR_TEXT, v.len() as u64, out);
+            out.extend_from_slice(v.as_bytes());
+        }
+        Value::Bin(v) => {
+            encode_head(MAJOR_BYTES, v.len() as u64, out);
+            out.extend_from_slice(v);
+        }
+        Value::Array(items) => {
+            encode_head(MAJOR_ARRAY, items.len() as u64, out);
+            for item in items {
+                encode_value(item, out)?;
+            }
+        }
+        Value::Map(entries) => {
+            encode_head(MAJOR_MAP, entries.len() as u64, out);
+            for (key, item) in entries {
+                encode_head(MAJOR_TEXT, key.len() as u64, out);
+                out.extend_from_slice(key.as_bytes());
+                encode_value(item, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+    if data.len() < len {
+        return Err(Error::Invalid("CBOR data truncated"));
+    }
+    let (prefix, rest) = data.split_at(len);
+    *data = rest;
+    Ok(prefix)
+}
+
+fn take_array<const N: usize>(data: &mut &[u8]) -> Result<[u8; N], Error> {
+    let mut buf = [0u8; N];
+    buf.copy_from_slice(take(data, N)?);
+    Ok(buf)
+}
+
+fn read_arg(data: &mut &[u8], info: u8) -> Result<u64, Error> {
+    Ok(match info {
+        0..=23 => info as u64,
+        24 => take_array::<1>(data)?[0] as u64,
+        25 => u16::from_be_bytes(take_array(data)?) as u64,
+        26 => u32::from_be_bytes(take_array(data)?) as u64,
+        27 => u64::from_be_bytes(take_array(data)?),
+        31 => return Err(Error::Unsupported("CBOR indefinite-length items")),
+        _ => return Err(Error::Invalid("reserved CBOR additional information")),
+    })
+}
+
+fn read_len(data: &mut &[u8], info: u8) -> Result<usize, Error> {
+    usize::try_from(read_arg(data, info)?).map_err(|_| Error::DataOverflow("CBOR item too large"))
+}
+
+fn read_text(data: &mut &[u8], len: usize) -> Result<String, Error> {
+    core::str::from_utf8(take(data, len)?)
+        .map(String::from)
+        .map_err(|_| Error::Invalid("CBOR text is not UTF-8"))
+}
+
+fn decode_value(data: &mut &[u8], depth: usize) -> Result<Value, Error> {
+    if depth > MAX_VALUE_DEPTH {
+        return Err(Error::Invalid("CBOR item nested too deeply"));
+    }
+    let initial = take_array::<1>(data)?[0];
+    let (major, info) = (initial >> 5, initial & 0x1F);
+    Ok(match major {
+        MAJOR_UINT => Value::Int(read_arg(data, info)? as i128),
+        MAJOR_NINT => Value::Int(-1 - read_arg(data, info)? as i128),
+        MAJOR_BYTES => {
+            let len = read_len(data, info)?;
+            Value::Bin(take(data, len)?.to_vec())
+        }
+        MAJOR_TEXT => {
+            let len = read_len(data, info)?;
+            Value::Str(read_text(data, len)?)
+        }
+        MAJOR_ARRAY => {
+            let len = read_len(data, info)?;
+            // Every item takes at least one byte, which bounds the preallocation.
+            let mut items = Vec::with_capacity(len.min(data.len()));
+            for _ in 0..len {
+                items.push(decode_value(data, depth + 1)?);
+            }
+            Value::Array(items)
+        }
+        MAJOR_MAP => {
+            let len = read_len(data, info)?;
+            let mut entries = Vec::with_capacity(len.min(data.len() / 2));
+            for _ in 0..len {
+                let Value::Str(key) = decode_value(data, depth + 1)? else {
+                    return Err(Error::Invalid("CBOR map keys must be text strings"));
+                };
+                entries.push((key, decode_value(data, depth + 1)?));
+            }
+            Value::Map(entries)
+        }
+        MAJOR_SIMPLE => match info {
+            20 => Value::Bool(false),
+            21 => Value::Bool(true),
+            22 => Value::Nil,
+            26 => Value::F32(f32::from_bits(u32::from_be_bytes(take_array(data)?))),
+            27 => Value::F64(f64::from_bits(u64::from_be_bytes(take_array(data)?))),
+            _ => return Err(Error::Unsupported("CBOR simple value")),
+        },
+        _ => return Err(Error::Unsupported("CBOR tagged items")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::osc::{OscArg, OscBundle, OscMessage};
+    use alloc::vec;
+
+    #[test]
+    fn encodes_message_schema() {
+        let packet = OscPacket::from(OscMessage::new("/a", vec![OscArg::Int(-2)]));
+        let bytes = packet.to_cbor().unwrap();
+        let mut expected = vec![0xA4, 0x61, b'k', 0x63, b'm', b's', b'g'];
+        expected.extend_from_slice(&[0x61, b'p', 0x62, b'/', b'a']);
+        expected.extend_from_slice(&[0x61, b't', 0x62, b',', b'i']);
+        expected.extend_from_slice(&[0x61, b'a', 0x81, 0x21]);
+        assert_eq!(bytes, expected);
+        assert_eq!(OscPacket::from_cbor(&bytes).unwrap(), packet);
+    }
+
+    #[test]
+    fn bundle_roundtrips() {
+        let packet = OscPacket::from(OscBundle::new(
+            u64::MAX,
+            vec![OscMessage::new("/b", vec![OscArg::Long(i64::MIN), OscArg::Nil]).into()],
+        ));
+        let bytes = packet.to_cbor().unwrap();
+        assert_eq!(OscPacket::from_cbor(&bytes).unwrap(), packet);
+    }
+
+    #[test]
+    fn rejects_indefinite_lengths() {
+        assert!(OscPacket::from_cbor(&[0xBF, 0xFF]).is_err());
+    }
+}
@@ -0,0 +1,294 @@
+//! MessagePack encoding of the OSC schema (`OscFormat = 0x01`).
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::value::{packet_to_value, value_to_packet, Value, MAX_VALUE_DEPTH};
+use super::OscPacket;
+use crate::Error;
+
+impl OscPacket {
+    /// Encode using the MSGPACK schema.
+    pub fn to_msgpack(&self) -> Result<Vec<u8>, Error> {
+        let mut out = Vec::new();
+        encode_value(&packet_to_value(self), &mut out)?;
+        Ok(out)
+    }
+
+    /// Decode a MSGPACK schema payload.
+    pub fn from_msgpack(data: &[u8]) -> Result<Self, Error> {
+        let mut cursor = data;
+        let value = decode_value(&mut cursor, 0)?;
+        if !cursor.is_empty() {
+            return Err(Error::Invalid("trailing bytes after MessagePack value"));
+        }
+        value_to_packet(value)
+    }
+}
+
+/// Write a length header using the smallest available form: the fix form
+/// `(base, limit)`, then the 8-, 16- and 32-bit markers.
+fn encode_len(
+    len: usize,
+    fix: Option<(u8, usize)>,
+    marker8: Option<u8>,
+    [marker16, marker32]: [u8; 2],
+    out: &mut Vec<u8>,
+) -> Result<(), Error> {
+    match (fix, marker8) {
+        (Some((base, limit)), _) if len < limit => out.push(base | len as u8),
+        (_, Some(marker8)) if len <= u8::MAX as usize => {
+            out.push(marker8);
+            out.push(len as u8);
+        }
+        _ if len <= u16::MAX as usize => {
+            out.push(marker16);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        _ => {
+            let len = u32::try_from(len)
+                .map_err(|_| Error::DataOverflow("MessagePack item too large"))?;
+            out.push(marker32);
+            out.extend_from_slice(&len.to_be_bytes());
+        }
+    }
+    Ok(())
+}
+
+fn encode_int(value: i128, out: &mut Vec<u8>) -> Result<(), Error> {
+    if (0..=0x7F).contains(&value) {
+        out.push(value as u8);
+    } else if (-32..0).contains(&value) {
+        out.push(value as i8 as u8);
+    } else if value >= 0 {
+        if let Ok(v) = u8::try_from(value) {
+            out.push(0xCC);
+            out.push(v);
+        } else if let Ok(v) = u16::try_from(value) {
+            out.push(0xCD);
+            out.extend_from_slice(&v.to_be_bytes());
+        } else if let Ok(v) = u32::try_from(value) {
+            out.push(0xCE);
+            out.extend_from_slice(&v.to_be_bytes());
+        } else {
+            let v = u64::try_from(value)
+                .map_err(|_| Error::DataOverflow("integer exceeds MessagePack range"))?;
+            out.push(0xCF);
+            out.extend_from_slice(&v.to_be_bytes());
+        }
+    } else if let Ok(v) = i8::try_from(value) {
+        out.push(0xD0);
+        out.push(v as u8);
+    } else if let Ok(v) = i16::try_from(value) {
+        out.push(0xD1);
+        out.extend_from_slice(&v.to_be_bytes());
+    } else if let Ok(v) = i32::try_from(value) {
+        out.push(0xD2);
+        out.extend_from_slice(&v.to_be_bytes());
+    } else {
+        let v = i64::try_from(value)
+            .map_err(|_| Error::DataOverflow("integer exceeds MessagePack range"))?;
+        out.push(0xD3);
+        out.extend_from_slice(&v.to_be_bytes());
+    }
+    Ok(())
+}
+
+fn encode_str(value: &str, out: &mut Vec<u8>) -> Result<(), Error> {
+    encode_len(value.len(), Some((0xA0, 32)), Some(0xD9), [0xDA, 0xDB], out)?;
+    out.extend_from_slice(value.as_bytes());
+    Ok(())
+}
+
+fn encode_value(value: &Value, out: &mut Vec<u8>) -> Result<(), Error> {
+    match value {
+        Value::Nil => out.push(0xC0),
+        Value::Bool(false) => out.push(0xC2),
+        Value::Bool(true) => out.push(0xC3),
+        Value::Int(v) => encode_int(*v, out)?,
+        Value::F32(v) => {
+            out.push(0xCA);
+            out.extend_from_slice(&v.to_bits().to_be_bytes());
+        }
+        Value::F64(v) => {
+            out.push(0xCB);
+            out.extend_from_slice(&v.to_bits().to_be_bytes());
+        }
+        Value::Str(v) => encode_str(v, out)?,
+        Value::Bin(v) => {
+            encode_len(v.len(), None, Some(0xC4), [0xC5, 0xC6], out)?;
+            out.extend_from_slice(v);
+        }
+        Value::Array(items) => {
+            encode_len(items.len(), Some((0x90, 16)), None, [0xDC, 0xDD], out)?;
+            for item in items {
+                encode_value(item, out)?;
+            }
+        }
+        Value::Map(entries) => {
+            encode_len(entries.len(), Some((0x80, 16)), None, [0xDE, 0xDF], out)?;
+            for (key, item) in entries {
+                encode_str(key, out)?;
+                encode_value(item, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+    if data.len() < len {
+        return Err(Error::Invalid("MessagePack data truncated"));
+    }
+    let (prefix, rest) = data.split_at(len);
+    *data = rest;
+    Ok(prefix)
+}
+
+fn take_array<const N: usize>(data: &mut &[u8]) -> Result<[u8; N], Error> {
+    let mut buf = [0u8; N];
+    buf.copy_from_slice(take(data, N)?);
+    Ok(buf)
+}
+
+fn read_len(data: &mut &[u8], width: u8) -> Result<usize, Error> {
+    Ok(match width {
+        1 => take_array::<1>(data)?[0] as usize,
+        2 => u16::from_be_bytes(take_array(data)?) as usize,
+        _ => u32::from_be_bytes(take_array(data)?) as usize,
+    })
+}
+
+fn read_string(data: &mut &[u8], len: usize) -> Result<String, Error> {
+    let bytes = take(data, len)?;
+    core::str::from_utf8(bytes)
+        .map(String::from)
+        .map_err(|_| Error::Invalid("MessagePack string is not UTF-8"))
+}
+
+fn decode_value(data: &mut &[u8], depth: usize) -> Result<Value, Error> {
+    if depth > MAX_VALUE_DEPTH {
+        return Err(Error::Invalid("MessagePack value nested too deeply"));
+    }
+    let marker = take_array::<1>(data)?[0];
+    Ok(match marker {
+        0x00..=0x7F => Value::Int(marker as i128),
+        0x80..=0x8F => decode_map(data, (marker & 0x0F) as usize, depth)?,
+        0x90..=0x9F => decode_array(data, (marker & 0x0F) as usize, depth)?,
+        0xA0..=0xBF => Value::Str(read_string(data, (marker & 0x1F) as usize)?),
+        0xC0 => Value::Nil,
+        0xC2 => Value::Bool(false),
+        0xC3 => Value::Bool(true),
+        0xC4..=0xC6 => {
+            let len = read_len(data, 1 << (marker - 0xC4))?;
+            Value::Bin(take(data, len)?.to_vec())
+        }
+        0xCA => Value::F32(f32::from_bits(u32::from_be_bytes(take_array(data)?))),
+        0xCB => Value::F64(f64::from_bits(u64::from_be_bytes(take_array(data)?))),
+        0xCC => Value::Int(take_array::<1>(data)?[0] as i128),
+        0xCD => Value::Int(u16::from_be_bytes(take_array(data)?) as i128),
+        0xCE => Value::Int(u32::from_be_bytes(take_array(data)?) as i128),
+        0xCF => Value::Int(u64::from_be_bytes(take_array(data)?) as i128),
+        0xD0 => Value::Int(i8::from_be_bytes(take_array(data)?) as i128),
+        0xD1 => Value::Int(i16::from_be_bytes(take_array(data)?) as i128),
+        0xD2 => Value::Int(i32::from_be_bytes(take_array(data)?) as i128),
+        0xD3 => Value::Int(i64::from_be_bytes(take_array(data)?) as i128),
+        0xD9..=0xDB => {
+            let len = read_len(data, 1 << (marker - 0xD9))?;
+            Value::Str(read_string(data, len)?)
+        }
+        0xDC | 0xDD => {
+            let len = read_len(data, if marker == 0xDC { 2 } else { 4 })?;
+            decode_array(data, len, depth)?
+        }
+        0xDE | 0xDF => {
+            let len = read_len(data, if marker == 0xDE { 2 } else { 4 })?;
+            decode_map(data, len, depth)?
+        }
+        0xE0..=0xFF => Value::Int(marker as i8 as i128),
+        _ => return Err(Error::Unsupported("MessagePack extension types")),
+    })
+}
+
+fn decode_array(data: &mut &[u8], len: usize, depth: usize) -> Result<Value, Error> {
+    // Every item takes at least one byte, which bounds the preallocation.
+    let mut items = Vec::with_capacity(len.min(data.len()));
+    for _ in 0..len {
+        items.push(decode_value(data, depth + 1)?);
+    }
+    Ok(Value::Array(items))
+}
+
+fn decode_map(data: &mut &[u8], len: usize, depth: usize) -> Result<Value, Error> {
+    let mut entries = Vec::with_capacity(len.min(data.len() / 2));
+    for _ in 0..len {
+        let Value::Str(key) = decode_value(data, depth + 1)? else {
+            return Err(Error::Invalid("MessagePack map keys must be strings"));
+        };
+        entries.push((key, decode_value(data, depth + 1)?));
+    }
+    Ok(Value::Map(entries))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::osc::{OscArg, OscMessage};
+    use alloc::vec;
+
+    #[test]
+    fn encodes_message_schema() {
+        let packet = OscPacket::from(OscMessage::new("/a", vec![OscArg::Int(1)]));
+        let bytes = packet.to_msgpack().unwrap();
+        let mut expected = vec![0x84, 0xA1, b'k', 0xA3, b'm', b's', b'g'];
+        expected.extend_from_slice(&[0xA1, b'p', 0xA2, b'/', b'a']);
+        expected.extend_from_slice(&[0xA1, b't', 0xA2, b',', b'i']);
+        expected.extend_from_slice(&[0xA1, b'a', 0x91, 0x01]);
+        assert_eq!(bytes, expected);
+        assert_eq!(OscPacket::from_msgpack(&bytes).unwrap(), packet);
+    }
+
+    #[test]
+    fn integer_widths_roundtrip() {
+        for value in [
+            0i128,
+            127,
+            128,
+            -1,
+            -32,
+            -33,
+            70_000,
+            -70_000,
+            u64::MAX as i128,
+            i64::MIN as i128,
+        ] {
+            let mut out = Vec::new();
+            encode_int(value, &mut out).unwrap();
+            let mut cursor = &out[..];
+            assert_eq!(decode_value(&mut cursor, 0).unwrap(), Value::Int(value));
+            assert!(cursor.is_empty());
+        }
+    }
+
+    #[test]
+    fn accepts_reordered_keys_and_wide_lengths() {
+        // { "a": [], "p": "/x", "k": "msg", "t": "," } with a map16 header
+        let mut data = vec![0xDE, 0x00, 0x04];
+        data.extend_from_slice(&[0xA1, b'a', 0xDC, 0x00, 0x00]);
+        data.extend_from_slice(&[0xA1, b'p', 0xD9, 0x02, b'/', b'x']);
+        data.extend_from_slice(&[0xA1, b'k', 0xA3, b'm', b's', b'g']);
+        data.extend_from_slice(&[0xA1, b't', 0xA1, b',']);
+        assert_eq!(
+            OscPacket::from_msgpack(&data).unwrap(),
+            OscPacket::from(OscMessage::new("/x", vec![]))
+        );
+    }
+
+    #[test]
+    fn rejects_mismatched_values() {
+        let packet = OscPacket::from(OscMessage::new("/a", vec![OscArg::Int(1)]));
+        let mut bytes = packet.to_msgpack().unwrap();
+        *bytes.last_mut().unwrap() = 0xC0;
+        assert!(OscPacket::from_msgpack(&bytes).is_err());
+    }
+}
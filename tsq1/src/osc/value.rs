@@ -0,0 +1,217 @@
+//! Self-describing value tree shared by the MSGPACK and CBOR OSC formats, and
+//! its mapping to the `{ "k", "p", "t", "a", "ntp" }` schema of spec §4.1.
+//!
+//! Messages map to `{ "k": "msg", "p": address, "t": type tags, "a": args }`
+//! and bundles to `{ "k": "bun", "ntp": timetag, "a": [element, ...] }`.
+//! Arguments are interpreted through the type tag string, which keeps the
+//! conversion lossless: `f`/`d` use 32/64-bit floats, `b` and `m` are binary,
+//! `c` is a one-character string, `t` and `r` are unsigned integers, `T`/`F`
+//! are booleans, `N`/`I` are nil and `[...]` is a nested array.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::{OscArg, OscBundle, OscMessage, OscPacket, MAX_DEPTH};
+use crate::Error;
+
+/// Maximum nesting accepted by the value decoders. A bundle level uses two
+/// value levels (map and element array), arrays use one.
+pub(crate) const MAX_VALUE_DEPTH: usize = 2 * MAX_DEPTH + 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Nil,
+    Bool(bool),
+    Int(i128),
+    F32(f32),
+    F64(f64),
+    Str(String),
+    Bin(Vec<u8>),
+    Array(Vec<Value>),
+    Map(Vec<(String, Value)>),
+}
+
+pub(crate) fn packet_to_value(packet: &OscPacket) -> Value {
+    match packet {
+        OscPacket::Message(message) => Value::Map(alloc::vec![
+            (String::from("k"), Value::Str(String::from("msg"))),
+            (String::from("p"), Value::Str(message.address.clone())),
+            (String::from("t"), Value::Str(message.type_tags())),
+            (
+                String::from("a"),
+                Value::Array(message.args.iter().map(arg_to_value).collect()),
+            ),
+        ]),
+        OscPacket::Bundle(bundle) => Value::Map(alloc::vec![
+            (String::from("k"), Value::Str(String::from("bun"))),
+            (String::from("ntp"), Value::Int(bundle.timetag as i128)),
+            (
+                String::from("a"),
+                Value::Array(bundle.elements.iter().map(packet_to_value).collect()),
+            ),
+        ]),
+    }
+}
+
+fn arg_to_value(arg: &OscArg) -> Value {
+    match arg {
+        OscArg::Int(v) => Value::Int(*v as i128),
+        OscArg::Float(v) => Value::F32(*v),
+        OscArg::String(v) | OscArg::Symbol(v) => Value::Str(v.clone()),
+        OscArg::Blob(v) => Value::Bin(v.clone()),
+        OscArg::Long(v) => Value::Int(*v as i128),
+        OscArg::Double(v) => Value::F64(*v),
+        OscArg::Timetag(v) => Value::Int(*v as i128),
+        OscArg::Char(c) => Value::Str(String::from(*c)),
+        OscArg::Rgba(v) => Value::Int(*v as i128),
+        OscArg::Midi(bytes) => Value::Bin(bytes.to_vec()),
+        OscArg::True => Value::Bool(true),
+        OscArg::False => Value::Bool(false),
+        OscArg::Nil | OscArg::Impulse => Value::Nil,
+        OscArg::Array(items) => Value::Array(items.iter().map(arg_to_value).collect()),
+    }
+}
+
+pub(crate) fn value_to_packet(value: Value) -> Result<OscPacket, Error> {
+    value_to_packet_at(value, 0)
+}
+
+fn value_to_packet_at(value: Value, depth: usize) -> Result<OscPacket, Error> {
+    if depth > MAX_DEPTH {
+        return Err(Error::Invalid("OSC packet nested too deeply"));
+    }
+    let Value::Map(entries) = value else {
+        return Err(Error::Invalid("OSC schema value must be a map"));
+    };
+
+    let mut kind = None;
+    let mut address = None;
+    let mut tags = None;
+    let mut args = None;
+    let mut ntp = None;
+    for (key, value) in entries {
+        match key.as_str() {
+            "k" => kind = Some(value),
+            "p" => address = Some(value),
+            "t" => tags = Some(value),
+            "a" => args = Some(value),
+            "ntp" => ntp = Some(value),
+            _ => {}
+        }
+    }
+
+    let items = match args {
+        Some(Value::Array(items)) => items,
+        None => Vec::new(),
+        Some(_) => return Err(Error::Invalid("OSC schema \"a\" must be an array")),
+    };
+
+    match kind {
+        Some(Value::Str(k)) if k == "msg" => {
+            let Some(Value::Str(address)) = address else {
+                return Err(Error::Invalid("OSC schema message requires string \"p\""));
+            };
+            let tags = match tags {
+                Some(Value::Str(tags)) => tags,
+                None if items.is_empty() => String::from(","),
+                _ => return Err(Error::Invalid("OSC schema message requires string \"t\"")),
+            };
+            let tags = tags
+                .strip_prefix(',')
+                .ok_or(Error::Invalid("OSC type tag string must start with ','"))?;
+            let mut tags = tags.as_bytes();
+            let mut items = items.into_iter();
+            let args = values_to_args(&mut tags, &mut items, 0)?;
+            if items.next().is_some() {
+                return Err(Error::Invalid("OSC schema has more values than type tags"));
+            }
+            Ok(OscPacket::Message(OscMessage { address, args }))
+        }
+        Some(Value::Str(k)) if k == "bun" => {
+            let timetag = match ntp {
+                Some(Value::Int(v)) => {
+                    u64::try_from(v).map_err(|_| Error::Invalid("OSC timetag out of range"))?
+                }
+                None => 1,
+                Some(_) => return Err(Error::Invalid("OSC schema \"ntp\" must be an integer")),
+            };
+            let elements = items
+                .into_iter()
+                .map(|item| value_to_packet_at(item, depth + 1))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(OscPacket::Bundle(OscBundle { timetag, elements }))
+        }
+        _ => Err(Error::Invalid(
+            "OSC schema \"k\" must be \"msg\" or \"bun\"",
+        )),
+    }
+}
+
+fn values_to_args(
+    tags: &mut &[u8],
+    values: &mut impl Iterator<Item = Value>,
+    depth: usize,
+) -> Result<Vec<OscArg>, Error> {
+    let mut args = Vec::new();
+    while let Some((&tag, rest)) = tags.split_first() {
+        *tags = rest;
+        if tag == b']' && depth > 0 {
+            return Ok(args);
+        }
+        let value = values
+            .next()
+            .ok_or(Error::Invalid("OSC schema has fewer values than type tags"))?;
+        args.push(value_to_arg(tag, value, tags, depth)?);
+    }
+    if depth > 0 {
+        return Err(Error::Invalid("unterminated OSC array"));
+    }
+    Ok(args)
+}
+
+fn value_to_arg(tag: u8, value: Value, tags: &mut &[u8], depth: usize) -> Result<OscArg, Error> {
+    const MISMATCH: Error = Error::Invalid("OSC schema value does not match type tag");
+    let int = |value: Value| match value {
+        Value::Int(v) => Ok(v),
+        _ => Err(MISMATCH),
+    };
+    Ok(match (tag, value) {
+        (b'i', v) => OscArg::Int(i32::try_from(int(v)?).map_err(|_| MISMATCH)?),
+        (b'h', v) => OscArg::Long(i64::try_from(int(v)?).map_err(|_| MISMATCH)?),
+        (b't', v) => OscArg::Timetag(u64::try_from(int(v)?).map_err(|_| MISMATCH)?),
+        (b'r', v) => OscArg::Rgba(u32::try_from(int(v)?).map_err(|_| MISMATCH)?),
+        (b'f', Value::F32(v)) => OscArg::Float(v),
+        (b'd', Value::F64(v)) => OscArg::Double(v),
+        (b's', Value::Str(v)) => OscArg::String(v),
+        (b'S', Value::Str(v)) => OscArg::Symbol(v),
+        (b'b', Value::Bin(v)) => OscArg::Blob(v),
+        (b'm', Value::Bin(v)) => {
+            OscArg::Midi(<[u8; 4]>::try_from(v.as_slice()).map_err(|_| MISMATCH)?)
+        }
+        (b'c', Value::Str(v)) => {
+            let mut chars = v.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if c.is_ascii() => OscArg::Char(c),
+                _ => return Err(MISMATCH),
+            }
+        }
+        (b'T', Value::Bool(true)) => OscArg::True,
+        (b'F', Value::Bool(false)) => OscArg::False,
+        (b'N', Value::Nil) => OscArg::Nil,
+        (b'I', Value::Nil) => OscArg::Impulse,
+        (b'[', Value::Array(items)) => {
+            if depth >= MAX_DEPTH {
+                return Err(Error::Invalid("OSC array nested too deeply"));
+            }
+            let mut items = items.into_iter();
+            let nested = values_to_args(tags, &mut items, depth + 1)?;
+            if items.next().is_some() {
+                return Err(Error::Invalid("OSC schema has more values than type tags"));
+            }
+            OscArg::Array(nested)
+        }
+        (b'f' | b'd' | b's' | b'S' | b'b' | b'm' | b'c', _)
+        | (b'T' | b'F' | b'N' | b'I' | b'[', _) => return Err(MISMATCH),
+        _ => return Err(Error::Invalid("unsupported OSC type tag")),
+    })
+}
@@ -0,0 +1,491 @@
+//! `"SMFP"` chunk recording the layout of an archived Standard MIDI File.
+//!
+//! Archival imports ([`MidiToTsqOptions::archival`](crate::MidiToTsqOptions))
+//! append this chunk so that converting back reproduces the original `.mid`
+//! byte for byte. The header, foreign chunks and trailing bytes are kept
+//! verbatim; each `MTrk` chunk refers to the `"TRK "` chunk converted from
+//! it, in order.
+//!
+//! The payload is `context:u64` followed by segments up to the end of the
+//! chunk (integers little endian):
+//! - `0x00 len:u32 bytes`: bytes copied verbatim.
+//! - `0x01 fingerprint:u64`: an `MTrk` chunk that re-encodes exactly.
+//! - `0x02 fingerprint:u64 len:u32 bytes`: an `MTrk` chunk stored verbatim
+//!   because re-encoding would change it (running status, non-minimal
+//!   lengths, split SysEx packets, truncation, ...).
+//!
+//! Fingerprints are FNV-1a hashes of the `"TRK "` chunk data, and `context`
+//! hashes the header fields and `"TMAP"` chunks the export depends on. A
+//! track whose fingerprint no longer matches is re-encoded as usual, and the
+//! chunk is ignored altogether when the context or the SMF division differ, so
+//! edited files still convert correctly.
+
+use alloc::vec::Vec;
+
+use midly::{Header as SmfHeader, Smf, Timing, TrackEvent};
+
+use crate::iter::{self, ChunkRef};
+use crate::timecode::SmpteTiming;
+use crate::{convert_tsq_to_smf, smpte_fps, write, Error, TsqToMidiOptions};
+
+/// Chunk ID of the provenance chunk.
+pub(crate) const CHUNK_ID: [u8; 4] = *b"SMFP";
+
+const SEG_RAW: u8 = 0x00;
+const SEG_TRACK: u8 = 0x01;
+const SEG_TRACK_VERBATIM: u8 = 0x02;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// A piece of the original SMF.
+enum Segment<'a> {
+    Raw(&'a [u8]),
+    /// An `MTrk` chunk and, if it does not re-encode exactly, its bytes.
+    Track {
+        fingerprint: u64,
+        original: Option<&'a [u8]>,
+    },
+}
+
+/// Append the provenance chunk of `midi` to `tsq`, which must be the output
+/// of converting `smf` (parsed from `midi`).
+pub(crate) fn record(midi: &[u8], smf: &Smf<'_>, tsq: &mut Vec<u8>) -> Result<(), Error> {
+    let payload = encode_payload(midi, smf, tsq)?;
+    write::push_chunk(tsq, &CHUNK_ID, &payload)
+}
+
+fn encode_payload(midi: &[u8], smf: &Smf<'_>, tsq: &[u8]) -> Result<Vec<u8>, Error> {
+    let pieces = split_smf(midi)?;
+    let track_count = pieces.iter().filter(|(is_track, _)| *is_track).count();
+    if track_count != smf.tracks.len() {
+        return Err(Error::Unsupported("SMF layout cannot be archived"));
+    }
+
+    let timecode = match smf.header.timing {
+        Timing::Timecode(fps, ticks_per_frame) => {
+            Some(SmpteTiming::new(smpte_fps(fps), ticks_per_frame))
+        }
+        Timing::Metrical(_) => None,
+    };
+    let exported = convert_tsq_to_smf(tsq, &TsqToMidiOptions { timecode }, None)?;
+    let chunks = tsq_chunks(tsq)?;
+    let mut tracks = track_data(&chunks);
+
+    let mut payload = Vec::new();
+    payload.extend_from_slice(&context(tsq, &chunks).to_le_bytes());
+    let mut exported_tracks = exported.tracks.iter();
+    for (is_track, bytes) in pieces {
+        if !is_track {
+            payload.push(SEG_RAW);
+            push_len_prefixed(&mut payload, bytes)?;
+            continue;
+        }
+        let (Some(data), Some(events)) = (tracks.next(), exported_tracks.next()) else {
+            return Err(Error::Invalid("track count mismatch"));
+        };
+        let exact = encode_track(&exported.header, events)? == bytes;
+        payload.push(if exact { SEG_TRACK } else { SEG_TRACK_VERBATIM });
+        payload.extend_from_slice(&fingerprint(FNV_OFFSET, data).to_le_bytes());
+        if !exact {
+            push_len_prefixed(&mut payload, bytes)?;
+        }
+    }
+
+    Ok(payload)
+}
+
+/// Write `smf` to `out` following the provenance chunk of `tsq`, if it has
+/// one that still applies. Returns whether anything was written.
+pub(crate) fn write(tsq: &[u8], smf: &Smf<'_>, out: &mut Vec<u8>) -> Result<bool, Error> {
+    let chunks = tsq_chunks(tsq)?;
+    let Some(chunk) = chunks.iter().find(|chunk| chunk.id == CHUNK_ID) else {
+        return Ok(false);
+    };
+    let payload = chunk.data;
+    let (recorded_context, segments) = parse_payload(payload)?;
+    if recorded_context != context(tsq, &chunks) {
+        return Ok(false);
+    }
+    let track_count = segments
+        .iter()
+        .filter(|segment| matches!(segment, Segment::Track { .. }))
+        .count();
+    if track_count != smf.tracks.len() {
+        return Ok(false);
+    }
+    match segments.first() {
+        Some(Segment::Raw(header)) if midly::parse(header)?.0.timing == smf.header.timing => {}
+        _ => return Ok(false),
+    }
+
+    let mut tracks = track_data(&chunks).zip(&smf.tracks);
+    for segment in segments {
+        match segment {
+            Segment::Raw(bytes) => out.extend_from_slice(bytes),
+            Segment::Track {
+                fingerprint: recorded,
+                original,
+            } => {
+                let (data, events) = tracks
+                    .next()
+                    .ok_or(Error::Invalid("track count mismatch"))?;
+                match original {
+                    Some(bytes) if fingerprint(FNV_OFFSET, data) == recorded => {
+                        out.extend_from_slice(bytes)
+                    }
+                    _ => out.extend_from_slice(&encode_track(&smf.header, events)?),
+                }
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// Split an SMF the way `midly` reads it, into runs of non-track bytes and
+/// `MTrk` chunks (`true`). A chunk whose length runs past the end of the
+/// file extends to the end, and bytes too short for a chunk header trail.
+fn split_smf(midi: &[u8]) -> Result<Vec<(bool, &[u8])>, Error> {
+    if !midi.starts_with(b"MThd") {
+        return Err(Error::Unsupported("archival mode needs a plain SMF"));
+    }
+    let mut pieces: Vec<(bool, &[u8])> = Vec::new();
+    let mut raw_start = 0;
+    let mut pos = 0;
+    while midi.len() - pos >= 8 {
+        let len = u32::from_be_bytes([midi[pos + 4], midi[pos + 5], midi[pos + 6], midi[pos + 7]]);
+        let end = (pos + 8).saturating_add(len as usize).min(midi.len());
+        if &midi[pos..pos + 4] == b"MTrk" {
+            if raw_start < pos {
+                pieces.push((false, &midi[raw_start..pos]));
+            }
+            pieces.push((true, &midi[pos..end]));
+            raw_start = end;
+        }
+        pos = end;
+    }
+    if raw_start < midi.len() {
+        pieces.push((false, &midi[raw_start..]));
+    }
+    Ok(pieces)
+}
+
+fn parse_payload(mut data: &[u8]) -> Result<(u64, Vec<Segment<'_>>), Error> {
+    let context = read_u64(&mut data)?;
+    let mut segments = Vec::new();
+    while let Some((&kind, rest)) = data.split_first() {
+        data = rest;
+        segments.push(match kind {
+            SEG_RAW => Segment::Raw(read_len_prefixed(&mut data)?),
+            SEG_TRACK => Segment::Track {
+                fingerprint: read_u64(&mut data)?,
+                original: None,
+            },
+            SEG_TRACK_VERBATIM => Segment::Track {
+                fingerprint: read_u64(&mut data)?,
+                original: Some(read_len_prefixed(&mut data)?),
+            },
+            _ => return Err(Error::Invalid("unknown SMF provenance segment")),
+        });
+    }
+    Ok((context, segments))
+}
+
+fn tsq_chunks(tsq: &[u8]) -> Result<Vec<ChunkRef<'_>>, Error> {
+    let (_, chunks) = iter::chunks(tsq)?;
+    Ok(chunks.collect::<Result<_, _>>()?)
+}
+
+fn track_data<'a>(chunks: &'a [ChunkRef<'a>]) -> impl Iterator<Item = &'a [u8]> + 'a {
+    chunks
+        .iter()
+        .filter(|chunk| chunk.id == *b"TRK ")
+        .map(|chunk| chunk.data)
+}
+
+/// Hash of everything besides the tracks that shapes the exported SMF.
+fn context(tsq: &[u8], chunks: &[ChunkRef<'_>]) -> u64 {
+    // PPQ, AbsUnit, Reserved and Flags; TrackCount is advisory.
+    let mut hash = fingerprint(FNV_OFFSET, &tsq[6..10]);
+    hash = fingerprint(hash, &tsq[12..14]);
+    for chunk in chunks.iter().filter(|chunk| chunk.id == *b"TMAP") {
+        hash = fingerprint(hash, &(chunk.data.len() as u32).to_le_bytes());
+        hash = fingerprint(hash, chunk.data);
+    }
+    hash
+}
+
+fn fingerprint(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes {
+        hash = (hash ^ byte as u64).wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+/// The `MTrk` chunk `midly` writes for `events`.
+fn encode_track(header: &SmfHeader, events: &[TrackEvent<'_>]) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::new();
+    midly::write(header, [events], &mut out).map_err(|_| Error::Invalid("failed to encode SMF"))?;
+    // Drop the 14-byte `MThd` chunk.
+    Ok(out.split_off(14))
+}
+
+fn push_len_prefixed(out: &mut Vec<u8>, bytes: &[u8]) -> Result<(), Error> {
+    let len = u32::try_from(bytes.len())
+        .map_err(|_| Error::DataOverflow("SMF provenance segment too large"))?;
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(bytes);
+    Ok(())
+}
+
+fn read_len_prefixed<'a>(data: &mut &'a [u8]) -> Result<&'a [u8], Error> {
+    let len = crate::take_slice(data, 4)?;
+    let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+    crate::take_slice(data, len)
+}
+
+fn read_u64(data: &mut &[u8]) -> Result<u64, Error> {
+    let bytes = crate::take_slice(data, 8)?;
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(bytes);
+    Ok(u64::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        convert_midi_to_tsq_vec_with_options, convert_tsq_to_midi_vec_with_options, read,
+        EventKind, MidiToTsqOptions, SmpteFps,
+    };
+    use alloc::vec;
+    use midly::{MidiMessage, TrackEventKind};
+
+    /// Deterministic generator for the SMF corpus.
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn below(&mut self, n: u32) -> u32 {
+            self.0 = self
+                .0
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            ((self.0 >> 33) as u32) % n
+        }
+
+        fn chance(&mut self, one_in: u32) -> bool {
+            self.below(one_in) == 0
+        }
+    }
+
+    /// Variable-length quantity, optionally with a redundant leading byte.
+    fn push_vlq(out: &mut Vec<u8>, value: u32, padded: bool) {
+        let mut bytes = vec![(value & 0x7F) as u8];
+        let mut rest = value >> 7;
+        while rest > 0 {
+            bytes.push((rest & 0x7F) as u8 | 0x80);
+            rest >>= 7;
+        }
+        if padded && bytes.len() < 4 {
+            bytes.push(0x80);
+        }
+        out.extend(bytes.iter().rev());
+    }
+
+    fn push_smf_chunk(out: &mut Vec<u8>, id: &[u8; 4], declared: u32, data: &[u8]) {
+        out.extend_from_slice(id);
+        out.extend_from_slice(&declared.to_be_bytes());
+        out.extend_from_slice(data);
+    }
+
+    /// Track data with running status, padded lengths, split SysEx packets
+    /// and sometimes no End of Track.
+    fn track_data(rng: &mut Lcg) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut running = None;
+        for _ in 0..rng.below(24) {
+            push_vlq(&mut out, rng.below(600), rng.chance(4));
+            match rng.below(9) {
+                0..=5 => {
+                    let kind = [0x80, 0x90, 0xB0, 0xC0, 0xE0][rng.below(5) as usize];
+                    let status = kind | rng.below(3) as u8;
+                    if running != Some(status) || rng.chance(2) {
+                        out.push(status);
+                    }
+                    running = Some(status);
+                    out.push(rng.below(128) as u8);
+                    if kind != 0xC0 {
+                        out.push(rng.below(128) as u8);
+                    }
+                }
+                6 => {
+                    out.extend_from_slice(&[0xFF, 0x01]);
+                    push_vlq(&mut out, 3, rng.chance(3));
+                    out.extend_from_slice(b"abc");
+                    running = None;
+                }
+                7 => {
+                    out.extend_from_slice(&[0xFF, 0x51, 0x03, 0x07]);
+                    out.extend_from_slice(&[rng.below(256) as u8, 0x20]);
+                    running = None;
+                }
+                _ => {
+                    if rng.chance(2) {
+                        out.extend_from_slice(&[0xF0, 0x03, 0x7E, 0x01, 0xF7]);
+                    } else {
+                        out.extend_from_slice(&[0xF0, 0x02, 0x43, 0x10]);
+                        push_vlq(&mut out, rng.below(10), false);
+                        out.extend_from_slice(&[0xF7, 0x02, 0x4C, 0xF7]);
+                    }
+                    running = None;
+                }
+            }
+        }
+        if !rng.chance(5) {
+            out.extend_from_slice(&[0x00, 0xFF, 0x2F, 0x00]);
+        }
+        out
+    }
+
+    /// A generated SMF and the timecode division it uses, if any.
+    fn generated_smf(seed: u64) -> (Vec<u8>, Option<SmpteTiming>) {
+        let rng = &mut Lcg(seed);
+        let track_count = 1 + rng.below(4) as u16;
+        let format: u16 = match track_count {
+            1 => rng.below(3) as u16,
+            _ => 1 + rng.below(2) as u16,
+        };
+        let (division, timecode) = if rng.chance(4) {
+            let (code, fps) = [
+                (24, SmpteFps::Fps24),
+                (25, SmpteFps::Fps25),
+                (29, SmpteFps::Fps29_97Drop),
+                (30, SmpteFps::Fps30),
+            ][rng.below(4) as usize];
+            let ticks_per_frame = 1 + rng.below(80) as u8;
+            let division = [(-code as i8) as u8, ticks_per_frame];
+            (division, Some(SmpteTiming::new(fps, ticks_per_frame)))
+        } else {
+            ((24 + rng.below(937) as u16).to_be_bytes(), None)
+        };
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&format.to_be_bytes());
+        let declared_tracks = track_count + rng.chance(6) as u16;
+        header.extend_from_slice(&declared_tracks.to_be_bytes());
+        header.extend_from_slice(&division);
+        if rng.chance(6) {
+            header.extend_from_slice(&[0, 0]);
+        }
+
+        let mut smf = Vec::new();
+        push_smf_chunk(&mut smf, b"MThd", header.len() as u32, &header);
+        let truncated = rng.chance(6);
+        for index in 0..track_count {
+            if rng.chance(4) {
+                push_smf_chunk(&mut smf, b"XFIH", 4, b"\x00\x01\x02\x03");
+            }
+            let data = track_data(rng);
+            let last = index + 1 == track_count;
+            let declared = data.len() as u32 + (last && truncated) as u32 * 16;
+            push_smf_chunk(&mut smf, b"MTrk", declared, &data);
+        }
+        if !truncated && rng.chance(3) {
+            smf.extend_from_slice(&[0x00, 0x0D, 0x0A]);
+        }
+        (smf, timecode)
+    }
+
+    #[test]
+    fn archival_roundtrip_reproduces_generated_corpus() {
+        let mut verbatim_tracks = 0;
+        for seed in 0..200 {
+            let (smf, timecode) = generated_smf(seed);
+            let options = MidiToTsqOptions {
+                archival: true,
+                extract_tempo_map: seed % 2 == 0,
+                ..Default::default()
+            };
+            let tsq = convert_midi_to_tsq_vec_with_options(&smf, &options)
+                .unwrap_or_else(|err| panic!("seed {seed}: {err}"));
+            let export = TsqToMidiOptions { timecode };
+            let roundtrip = convert_tsq_to_midi_vec_with_options(&tsq, &export).unwrap();
+            assert_eq!(roundtrip, smf, "seed {seed}");
+
+            // The chunk survives decoding and re-encoding the sequence.
+            let rewritten = read(&tsq).unwrap().to_vec().unwrap();
+            let roundtrip = convert_tsq_to_midi_vec_with_options(&rewritten, &export).unwrap();
+            assert_eq!(roundtrip, smf, "seed {seed}");
+
+            let chunks = tsq_chunks(&tsq).unwrap();
+            let chunk = chunks.iter().find(|chunk| chunk.id == CHUNK_ID).unwrap();
+            verbatim_tracks += parse_payload(chunk.data)
+                .unwrap()
+                .1
+                .iter()
+                .filter(|segment| {
+                    matches!(
+                        segment,
+                        Segment::Track {
+                            original: Some(_),
+                            ..
+                        }
+                    )
+                })
+                .count();
+        }
+        assert!(verbatim_tracks > 0);
+    }
+
+    #[test]
+    fn edited_tracks_are_reencoded() {
+        // Two tracks with a padded delta, which midly does not reproduce.
+        let track = [
+            0x00, 0x90, 0x3C, 0x64, 0x80, 0x10, 0x90, 0x3E, 0x64, 0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let mut smf = Vec::new();
+        push_smf_chunk(&mut smf, b"MThd", 6, &[0, 1, 0, 2, 0x01, 0xE0]);
+        for _ in 0..2 {
+            push_smf_chunk(&mut smf, b"MTrk", track.len() as u32, &track);
+        }
+        let options = MidiToTsqOptions {
+            archival: true,
+            ..Default::default()
+        };
+        let tsq = convert_midi_to_tsq_vec_with_options(&smf, &options).unwrap();
+        let plain = crate::convert_midi_to_tsq_vec(&smf).unwrap();
+        assert_ne!(crate::convert_tsq_to_midi_vec(&plain).unwrap(), smf);
+        assert_eq!(crate::convert_tsq_to_midi_vec(&tsq).unwrap(), smf);
+
+        // Only the edited track loses its original encoding.
+        let mut seq = read(&tsq).unwrap();
+        let EventKind::Midi(note) = &mut seq.tracks[1].events[1].kind else {
+            panic!("expected a MIDI event");
+        };
+        note.data2 = 1;
+        let edited = crate::convert_tsq_to_midi_vec(&seq.to_vec().unwrap()).unwrap();
+        let first_len = 14 + 8 + track.len();
+        assert_eq!(edited[..first_len], smf[..first_len]);
+        let parsed = Smf::parse(&edited).unwrap();
+        assert_eq!(
+            parsed.tracks[1][1].kind,
+            TrackEventKind::Midi {
+                channel: 0.into(),
+                message: MidiMessage::NoteOn {
+                    key: 0x3E.into(),
+                    vel: 1.into(),
+                },
+            }
+        );
+
+        // A different division makes the recorded layout useless.
+        let mut seq = read(&tsq).unwrap();
+        seq.header.ppq = 96;
+        let rescaled = seq.to_vec().unwrap();
+        seq.chunks.clear();
+        assert_eq!(
+            crate::convert_tsq_to_midi_vec(&rescaled).unwrap(),
+            crate::convert_tsq_to_midi_vec(&seq.to_vec().unwrap()).unwrap()
+        );
+    }
+}
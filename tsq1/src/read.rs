@@ -0,0 +1,622 @@
+//! Native TSQ1 decoder producing an owned [`Sequence`].
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::custom::CustomRegistry;
+use crate::event::{Domain, EventKind};
+use crate::iter::{self, ChunkRef, DecodeError};
+use crate::marker::Locator;
+use crate::sequence::{domain_index, Chunk, Header, Layout, RawChunk, Sequence, Track};
+use crate::sync::{SyncAnchor, SyncMap};
+use crate::tempo::{TempoEntry, TempoMap};
+use crate::{read_len_prefixed, read_u8, take_slice, Error};
+
+/// Parse a complete TSQ1 file.
+///
+/// Every chunk is decoded: `"TRK "` chunks become [`Track`]s, `"TMAP"`, `"SYNC"`
+/// and `"MARK"` become typed [`Chunk`]s and any other chunk is preserved as a
+/// [`RawChunk`]. Non-track chunks are kept in file order.
+pub fn read(data: &[u8]) -> Result<Sequence, Error> {
+    read_with_registry(data, &CustomRegistry::new())
+}
+
+/// Parse a complete TSQ1 file, decoding custom events whose `TypeID` has a
+/// codec in `registry`. See [`read`].
+pub fn read_with_registry(data: &[u8], registry: &CustomRegistry) -> Result<Sequence, Error> {
+    let (header, chunk_iter) = iter::chunks(data)?;
+    let mut tracks = Vec::new();
+    let mut chunks = Vec::new();
+    let mut ids = Vec::new();
+
+    for chunk in chunk_iter {
+        let chunk = chunk?;
+        ids.push(chunk.id);
+        match &chunk.id {
+            b"TRK " => tracks.push(read_track(chunk, &header, registry, None)?),
+            _ => chunks.push(
+                read_chunk(chunk.id, chunk.data)
+                    .map_err(|err| chunk.error_at(chunk.offset + 8, err))?,
+            ),
+        }
+    }
+
+    let mut seq = Sequence {
+        header,
+        tracks,
+        chunks,
+        layout: None,
+    };
+    seq.layout = Layout::recorded(ids, &seq);
+    Ok(seq)
+}
+
+/// A value decoded from damaged input, with every repair that was needed.
+#[derive(Debug)]
+pub struct Recovered<T> {
+    pub value: T,
+    /// One entry per skipped or truncated part, in input order. Empty if
+    /// the input was intact.
+    pub diagnostics: Vec<DecodeError>,
+}
+
+/// Parse a possibly damaged TSQ1 file, such as a recording cut short by a
+/// crash, keeping everything that can be decoded.
+///
+/// Only a bad file header fails. Otherwise a chunk running past the end of
+/// the data is cut short and a partial chunk header is dropped. A track
+/// ends at the first event that cannot be decoded, since the events after
+/// it cannot be found. Events that decode but carry an invalid payload are
+/// skipped, and so are non-track chunks that fail to decode.
+pub fn read_lenient(data: &[u8]) -> Result<Recovered<Sequence>, Error> {
+    read_lenient_with_registry(data, &CustomRegistry::new())
+}
+
+/// [`read_lenient`] with the custom event codecs in `registry`.
+pub fn read_lenient_with_registry(
+    data: &[u8],
+    registry: &CustomRegistry,
+) -> Result<Recovered<Sequence>, Error> {
+    let (header, chunk_iter) = iter::chunks(data)?;
+    let mut diagnostics = Vec::new();
+    let mut tracks = Vec::new();
+    let mut chunks = Vec::new();
+    let mut ids = Vec::new();
+
+    for chunk in chunk_iter.salvage(&mut diagnostics) {
+        let id = chunk.id;
+        match &id {
+            b"TRK " => {
+                let track = read_track(chunk, &header, registry, Some(&mut diagnostics));
+                tracks.push(track?);
+            }
+            _ => match read_chunk(chunk.id, chunk.data) {
+                Ok(decoded) => chunks.push(decoded),
+                Err(err) => {
+                    diagnostics.push(chunk.error_at(chunk.offset + 8, err));
+                    continue;
+                }
+            },
+        }
+        ids.push(id);
+    }
+    diagnostics.sort_by_key(|diagnostic| diagnostic.offset);
+
+    let mut value = Sequence {
+        header,
+        tracks,
+        chunks,
+        layout: None,
+    };
+    value.layout = Layout::recorded(ids, &value);
+    Ok(Recovered { value, diagnostics })
+}
+
+/// Decode a non-track chunk; unknown IDs are kept as [`RawChunk`]s.
+pub(crate) fn read_chunk(id: [u8; 4], data: &[u8]) -> Result<Chunk, Error> {
+    Ok(match &id {
+        b"TMAP" => Chunk::TempoMap(read_tempo_map(data)?),
+        b"SYNC" => Chunk::Sync(read_sync(data)?),
+        b"MARK" => Chunk::Markers(read_markers(data)?),
+        _ => Chunk::Unknown(RawChunk {
+            id,
+            data: data.to_vec(),
+        }),
+    })
+}
+
+/// Decode a `"TRK "` chunk. In the legacy MIDI layout program change and
+/// channel aftertouch read back with `data2 = 0`.
+///
+/// With `diagnostics`, errors are recorded there instead: an event with an
+/// invalid payload is skipped and undecodable data ends the track.
+fn read_track(
+    chunk: ChunkRef<'_>,
+    header: &Header,
+    registry: &CustomRegistry,
+    mut diagnostics: Option<&mut Vec<DecodeError>>,
+) -> Result<Track, Error> {
+    let mut events = Vec::new();
+    for (index, event) in chunk.events(header.flags).enumerate() {
+        let result = event.and_then(|event| {
+            let mut owned = event.to_event();
+            match &mut owned.kind {
+                EventKind::Osc(osc) => osc.validate(),
+                EventKind::Custom(custom) => registry.decode(custom),
+                _ => Ok(()),
+            }
+            .map(|()| owned)
+            .map_err(|err| chunk.error_at(event.offset, err).at_event(index))
+        });
+        match (result, diagnostics.as_deref_mut()) {
+            (Ok(event), _) => events.push(event),
+            (Err(err), Some(diagnostics)) => diagnostics.push(err),
+            (Err(err), None) => return Err(err.into()),
+        }
+    }
+    Ok(Track { events })
+}
+
+fn read_u64_le(data: &mut &[u8]) -> Result<u64, Error> {
+    let bytes = take_slice(data, 8)?;
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(bytes);
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_u32_le(data: &mut &[u8]) -> Result<u32, Error> {
+    let bytes = take_slice(data, 4)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+pub(crate) fn read_tempo_map(mut data: &[u8]) -> Result<TempoMap, Error> {
+    if !data.len().is_multiple_of(12) {
+        return Err(Error::Invalid("TMAP chunk length is not a multiple of 12"));
+    }
+    let mut entries = Vec::with_capacity(data.len() / 12);
+    while !data.is_empty() {
+        let tick = read_u64_le(&mut data)?;
+        let us_per_qn = read_u32_le(&mut data)?;
+        entries.push(TempoEntry { tick, us_per_qn });
+    }
+    Ok(TempoMap::from_entries(entries))
+}
+
+fn read_sync(mut data: &[u8]) -> Result<SyncMap, Error> {
+    if !data.len().is_multiple_of(16) {
+        return Err(Error::Invalid("SYNC chunk length is not a multiple of 16"));
+    }
+    let mut anchors = Vec::with_capacity(data.len() / 16);
+    while !data.is_empty() {
+        let tick = read_u64_le(&mut data)?;
+        let time_abs = read_u64_le(&mut data)?;
+        anchors.push(SyncAnchor { tick, time_abs });
+    }
+    Ok(SyncMap::from_anchors(anchors))
+}
+
+/// A locator entry up to and including `class`, borrowed from the chunk.
+struct LocatorFields<'a> {
+    pos_kind: Domain,
+    pos: u64,
+    name: &'a str,
+    class: u8,
+    /// Bytes taken, not counting a `color_rgba` that may follow.
+    len: usize,
+}
+
+/// Decode the locator entry at the start of `data` up to its `class` byte.
+fn read_locator_fields(data: &[u8]) -> Result<LocatorFields<'_>, Error> {
+    const TRUNCATED: Error = Error::Invalid("MARK entry runs past the end of the chunk");
+    let mut cursor = data;
+    let pos_kind = match read_u8(&mut cursor).map_err(|_| TRUNCATED)? {
+        0 => Domain::Musical,
+        1 => Domain::Absolute,
+        _ => return Err(Error::Invalid("MARK pos_kind must be 0 or 1")),
+    };
+    let pos = read_u64_le(&mut cursor).map_err(|_| TRUNCATED)?;
+    let name = read_len_prefixed(&mut cursor).map_err(|_| TRUNCATED)?;
+    let name = core::str::from_utf8(name).map_err(|_| Error::Invalid("MARK name is not UTF-8"))?;
+    let class = read_u8(&mut cursor).map_err(|_| TRUNCATED)?;
+    Ok(LocatorFields {
+        pos_kind,
+        pos,
+        name,
+        class,
+        len: data.len() - cursor.len(),
+    })
+}
+
+/// Decode a `"MARK"` chunk.
+///
+/// The trailing `color_rgba` field carries no presence flag, so it is
+/// present exactly when the chunk only splits into whole entries that way.
+/// `splits[i]` counts the ways `data[i..]` splits into entries, stopping at
+/// two. An entry that could end either with or without a color fails with
+/// an "ambiguous MARK entry" error rather than being guessed.
+pub(crate) fn read_markers(data: &[u8]) -> Result<Vec<Locator>, Error> {
+    read_markers_at(data).map_err(|(_, err)| err)
+}
+
+/// [`read_markers`], failing with the offset in `data` of the entry at
+/// fault, or of the bytes after it when they cannot follow it.
+pub(crate) fn read_markers_at(data: &[u8]) -> Result<Vec<Locator>, (usize, Error)> {
+    let len = data.len();
+    let mut splits = vec![0u8; len + 1];
+    splits[len] = 1;
+    let continuations = |splits: &[u8], end: usize| {
+        let with_color = splits.get(end + 4).copied().unwrap_or(0);
+        (splits[end], with_color)
+    };
+    for start in (0..len).rev() {
+        if let Ok(fields) = read_locator_fields(&data[start..]) {
+            let (plain, colored) = continuations(&splits, start + fields.len);
+            splits[start] = (plain + colored).min(2);
+        }
+    }
+
+    let mut locators = Vec::new();
+    let mut last: [Option<u64>; 2] = [None; 2];
+    let mut offset = 0;
+    while offset < len {
+        let start = offset;
+        let fields = read_locator_fields(&data[start..]).map_err(|err| (start, err))?;
+        offset += fields.len;
+        let color = match continuations(&splits, offset) {
+            (0, 0) => {
+                let message = "MARK entry is followed by malformed data";
+                return Err((offset, Error::Invalid(message)));
+            }
+            (_, 0) => None,
+            (0, _) => {
+                let color = read_u32_le(&mut &data[offset..]).map_err(|err| (offset, err))?;
+                offset += 4;
+                Some(color)
+            }
+            _ => {
+                let message = "ambiguous MARK entry: its color may or may not be present";
+                return Err((start, Error::Invalid(message)));
+            }
+        };
+        let slot = &mut last[domain_index(fields.pos_kind)];
+        if slot.is_some_and(|pos| pos > fields.pos) {
+            let message = "MARK locators are not sorted by position within each pos_kind";
+            return Err((start, Error::Invalid(message)));
+        }
+        *slot = Some(fields.pos);
+        locators.push(Locator {
+            pos_kind: fields.pos_kind,
+            pos: fields.pos,
+            name: String::from(fields.name),
+            class: fields.class,
+            color,
+        });
+    }
+    Ok(locators)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{CustomEvent, Event, MetaEvent, MidiEvent, OscEvent, OscFormat};
+    use crate::sequence::AbsUnit;
+    use crate::ErrorKind;
+    use alloc::string::ToString;
+
+    fn header_bytes(track_count: u16) -> Vec<u8> {
+        let mut out = Vec::new();
+        crate::write_header(&mut out, 480, AbsUnit::Microseconds, track_count, 0);
+        out
+    }
+
+    fn push_chunk(out: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+        out.extend_from_slice(id);
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(data);
+    }
+
+    fn locator_bytes(pos_kind: u8, pos: u64, name: &str, class: u8, color: Option<u32>) -> Vec<u8> {
+        let mut out = vec![pos_kind];
+        out.extend_from_slice(&pos.to_le_bytes());
+        crate::write_vlq(name.len() as u64, &mut out);
+        out.extend_from_slice(name.as_bytes());
+        out.push(class);
+        if let Some(color) = color {
+            out.extend_from_slice(&color.to_le_bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn reads_every_chunk_kind() {
+        let mut track = Vec::new();
+        track.extend_from_slice(&[0x00, 0x00, 0x00, 0x08]);
+        track.extend_from_slice(b"/a\0\0,\0\0\0");
+        track.extend_from_slice(&[0x81, 0x81, 0x10, 0x90, 0x3C, 0x64]);
+        track.extend_from_slice(&[0x01, 0x00, 0xC2, 0x05]);
+        track.extend_from_slice(&[0x03, 0x00, 0x02, 0x7D, 0x01]);
+        track.extend_from_slice(&[0x7E, 0x00, 0x42, 0x01, 0xAA]);
+        track.extend_from_slice(&[0x02, 0x00, 0x2F, 0x00]);
+
+        let mut tmap = Vec::new();
+        tmap.extend_from_slice(&0u64.to_le_bytes());
+        tmap.extend_from_slice(&500_000u32.to_le_bytes());
+
+        let mut sync = Vec::new();
+        sync.extend_from_slice(&960u64.to_le_bytes());
+        sync.extend_from_slice(&1_000_000u64.to_le_bytes());
+
+        let mut mark = locator_bytes(0, 1024, "Intro", 0x00, None);
+        mark.extend(locator_bytes(1, 90_000_000, "Drop", 0x20, Some(0xFF00FF00)));
+
+        let mut tsq = header_bytes(1);
+        push_chunk(&mut tsq, b"TRK ", &track);
+        push_chunk(&mut tsq, b"TMAP", &tmap);
+        push_chunk(&mut tsq, b"SYNC", &sync);
+        push_chunk(&mut tsq, b"MARK", &mark);
+        push_chunk(&mut tsq, b"XTRA", &[1, 2, 3]);
+
+        let seq = read(&tsq).expect("read succeeds");
+        assert_eq!(seq.header.ppq, 480);
+        assert_eq!(seq.header.track_count, 1);
+        assert_eq!(seq.tracks.len(), 1);
+
+        let events = &seq.tracks[0].events;
+        assert_eq!(events.len(), 6);
+        assert_eq!(
+            events[0].kind,
+            EventKind::Osc(OscEvent {
+                format: OscFormat::Raw,
+                data: b"/a\0\0,\0\0\0".to_vec(),
+            })
+        );
+        assert_eq!(events[1].domain, Domain::Absolute);
+        assert_eq!(events[1].delta, 0x90);
+        assert_eq!(
+            events[1].kind,
+            EventKind::Midi(MidiEvent::new(0x90, 0x3C, 0x64))
+        );
+        assert_eq!(
+            events[2].kind,
+            EventKind::Midi(MidiEvent::new(0xC2, 0x05, 0))
+        );
+        assert_eq!(events[3].kind, EventKind::SysEx(vec![0x7D, 0x01]));
+        assert_eq!(
+            events[4].kind,
+            EventKind::Custom(CustomEvent::new(0x42, vec![0xAA]))
+        );
+        assert_eq!(
+            events[5].kind,
+            EventKind::Meta(MetaEvent::new(0x2F, vec![]))
+        );
+
+        assert_eq!(seq.chunks.len(), 4);
+        assert_eq!(
+            seq.chunks[0],
+            Chunk::TempoMap(TempoMap::from_entries(vec![TempoEntry {
+                tick: 0,
+                us_per_qn: 500_000,
+            }]))
+        );
+        assert_eq!(
+            seq.chunks[1],
+            Chunk::Sync(SyncMap::from_anchors(vec![SyncAnchor {
+                tick: 960,
+                time_abs: 1_000_000,
+            }]))
+        );
+        match &seq.chunks[2] {
+            Chunk::Markers(locators) => {
+                assert_eq!(locators.len(), 2);
+                assert_eq!(locators[0].name, "Intro");
+                assert!(!locators[0].has_color());
+                assert_eq!(locators[1].pos, 90_000_000);
+                assert_eq!(locators[1].color, Some(0xFF00FF00));
+            }
+            other => panic!("expected markers, got {other:?}"),
+        }
+        assert_eq!(
+            seq.chunks[3],
+            Chunk::Unknown(RawChunk {
+                id: *b"XTRA",
+                data: vec![1, 2, 3],
+            })
+        );
+    }
+
+    #[test]
+    fn reads_both_midi_layouts() {
+        let legacy = [0x01, 0x00, 0xC2, 0x05, 0x01, 0x00, 0xD0, 0x40];
+        let padded = [0x01, 0x00, 0xC2, 0x05, 0x00, 0x01, 0x00, 0xD0, 0x40, 0x00];
+        for (track, flags) in [
+            (&legacy[..], 0),
+            (&padded[..], crate::FLAG_MIDI_THREE_BYTES),
+        ] {
+            let mut tsq = Vec::new();
+            crate::write_header(&mut tsq, 480, AbsUnit::Microseconds, 1, flags);
+            push_chunk(&mut tsq, b"TRK ", track);
+            let seq = read(&tsq).expect("read succeeds");
+            let kinds: Vec<&EventKind> = seq.tracks[0].events.iter().map(|e| &e.kind).collect();
+            assert_eq!(
+                kinds,
+                [
+                    &EventKind::Midi(MidiEvent::new(0xC2, 0x05, 0)),
+                    &EventKind::Midi(MidiEvent::new(0xD0, 0x40, 0)),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn reads_converter_output() {
+        let smf = midly::Smf {
+            header: midly::Header::new(
+                midly::Format::SingleTrack,
+                midly::Timing::Metrical(960.into()),
+            ),
+            tracks: vec![vec![midly::TrackEvent {
+                delta: 0.into(),
+                kind: midly::TrackEventKind::Meta(midly::MetaMessage::EndOfTrack),
+            }]],
+        };
+        let mut midi = Vec::new();
+        smf.write(&mut midi).expect("writing SMF succeeds");
+
+        let tsq = crate::convert_midi_to_tsq_vec(&midi).expect("conversion succeeds");
+        let seq = read(&tsq).expect("read succeeds");
+        assert_eq!(seq.header.ppq, 960);
+        assert_eq!(seq.tracks.len(), 1);
+        assert_eq!(
+            seq.tracks[0].events,
+            vec![Event::musical(
+                0,
+                EventKind::Meta(MetaEvent::new(0x2F, vec![]))
+            )]
+        );
+    }
+
+    #[test]
+    fn locator_colors_are_read_or_refused_but_not_guessed() {
+        let mark_file = |mark: &[u8]| {
+            let mut tsq = header_bytes(0);
+            push_chunk(&mut tsq, b"MARK", mark);
+            tsq
+        };
+
+        // Any class value, with or without a color.
+        let mut mark = locator_bytes(0, 0, "Intro", 0x20, Some(0xFF00FF00));
+        mark.extend(locator_bytes(1, 90_000_000, "Cue", 0x90, None));
+        mark.extend(locator_bytes(1, 90_000_000, "Out", 0xFF, Some(0x01020304)));
+        let seq = read(&mark_file(&mark)).unwrap();
+        let [Chunk::Markers(locators)] = &seq.chunks[..] else {
+            panic!("expected markers, got {:?}", seq.chunks);
+        };
+        let found: Vec<_> = locators.iter().map(|l| (l.class, l.color)).collect();
+        assert_eq!(
+            found,
+            [
+                (0x20, Some(0xFF00FF00)),
+                (0x90, None),
+                (0xFF, Some(0x01020304))
+            ]
+        );
+        assert_eq!(seq.to_vec().unwrap(), mark_file(&mark));
+
+        // The zero color reads as the start of an empty second entry and
+        // the empty second entry as the first one's color.
+        let mut mark = locator_bytes(0, 0, "", 0x00, Some(0));
+        mark.extend(locator_bytes(0, 0, "", 0x00, None));
+        let message = "ambiguous MARK entry: its color may or may not be present";
+        let err = read(&mark_file(&mark)).unwrap_err();
+        assert!(matches!(err.cause(), Error::Invalid(m) if *m == message));
+        let mut seq = Sequence::new(480);
+        let ambiguous = vec![
+            Locator::new(crate::EventTime::Musical(0), "").with_color(0),
+            Locator::new(crate::EventTime::Musical(0), ""),
+        ];
+        seq.chunks.push(Chunk::Markers(ambiguous));
+        assert!(matches!(seq.to_vec(), Err(Error::Invalid(m)) if m == message));
+    }
+
+    #[test]
+    fn rejects_unsorted_locators() {
+        let mut mark = locator_bytes(1, 500, "Late", 0x00, None);
+        mark.extend(locator_bytes(0, 0, "Start", 0x00, None));
+        mark.extend(locator_bytes(1, 100, "Early", 0x00, None));
+        let mut tsq = header_bytes(0);
+        push_chunk(&mut tsq, b"MARK", &mark);
+        let err = read(&tsq).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Invalid);
+        let at = err.location().unwrap();
+        assert_eq!(
+            (at.chunk_id, at.chunk_index, at.track),
+            (Some(*b"MARK"), Some(0), None)
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_chunk() {
+        let mut tsq = header_bytes(1);
+        tsq.extend_from_slice(b"TRK ");
+        tsq.extend_from_slice(&10u32.to_le_bytes());
+        tsq.extend_from_slice(&[0x02, 0x00]);
+        let err = read(&tsq).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Invalid);
+        assert_eq!(err.location().unwrap().offset, 22);
+    }
+
+    #[test]
+    fn errors_name_chunk_track_and_event() {
+        let mut tsq = header_bytes(2);
+        push_chunk(&mut tsq, b"XTRA", &[]);
+        push_chunk(&mut tsq, b"TRK ", &[]);
+        // Second event of the second track has a bad OSC RAW payload.
+        push_chunk(
+            &mut tsq,
+            b"TRK ",
+            &[0x02, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x01, b'x'],
+        );
+        let err = read(&tsq).unwrap_err();
+        assert!(matches!(err.cause(), Error::Invalid(_)));
+        let at = err.location().unwrap();
+        assert_eq!(
+            (at.offset, at.chunk_index, at.track, at.event),
+            (14 + 8 + 8 + 8 + 4, Some(2), Some(1), Some(1))
+        );
+        assert!(err
+            .to_string()
+            .ends_with("in chunk 2 \"TRK \", track 1, event 1"));
+    }
+
+    #[test]
+    fn lenient_read_salvages_damaged_files() {
+        let mut track = vec![0x01, 0x00, 0x90, 0x3C, 0x64];
+        // An OSC RAW event whose payload is not an OSC packet.
+        track.extend_from_slice(&[0x00, 0x00, 0x00, 0x01, b'x']);
+        track.extend_from_slice(&[0x01, 0x83, 0x60, 0x80, 0x3C, 0x00]);
+        track.extend_from_slice(&[0x03, 0x00, 0x10]);
+        track.extend_from_slice(&[0x7E; 16]);
+        let mut tsq = header_bytes(2);
+        push_chunk(&mut tsq, b"TRK ", &track);
+        push_chunk(&mut tsq, b"TMAP", &[0; 5]);
+        // The second track is cut inside its SysEx payload, as by a crash.
+        push_chunk(&mut tsq, b"TRK ", &track);
+        tsq.truncate(tsq.len() - 10);
+        assert!(read(&tsq).is_err());
+
+        let recovered = read_lenient(&tsq).unwrap();
+        let notes = vec![
+            Event::musical(0, EventKind::Midi(MidiEvent::new(0x90, 60, 100))),
+            Event::musical(480, EventKind::Midi(MidiEvent::new(0x80, 60, 0))),
+        ];
+        let mut complete = notes.clone();
+        complete.push(Event::musical(0, EventKind::SysEx(vec![0x7E; 16])));
+        assert_eq!(recovered.value.tracks[0].events, complete);
+        assert_eq!(recovered.value.tracks[1].events, notes);
+        assert!(recovered.value.chunks.is_empty());
+        let found: Vec<(usize, Option<usize>, Option<usize>)> = recovered
+            .diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.offset, diagnostic.track, diagnostic.event))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (27, Some(0), Some(1)),
+                (65, None, None),
+                (78, Some(1), None),
+                (83, Some(1), Some(1)),
+                (97, Some(1), Some(3)),
+            ]
+        );
+
+        // A partial chunk header at the end is dropped.
+        let mut tsq = header_bytes(0);
+        tsq.extend_from_slice(b"TMA");
+        let recovered = read_lenient(&tsq).unwrap();
+        assert_eq!(recovered.value.chunks.len(), 0);
+        assert_eq!(recovered.diagnostics[0].offset, 14);
+        assert!(read_lenient(b"TSQ1").is_err());
+    }
+}
@@ -0,0 +1,460 @@
+//! Incremental TSQ1 decoding from an [`io::Read`] source.
+//!
+//! [`Reader`] holds one chunk header and one event at a time, so files,
+//! sockets and pipes of any size are processed with bounded memory.
+
+use alloc::vec::Vec;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::event::{Event, EventKind, EK_CUSTOM, EK_META, EK_MIDI, EK_OSC, EK_SYSEX};
+use crate::iter::{parse_header, DecodeError, EventIter};
+use crate::read::read_chunk;
+use crate::sequence::{Chunk, Header, Track, HEADER_SIZE};
+use crate::Error;
+
+/// Position and size of a chunk announced by [`Reader::next_chunk`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ChunkHeader {
+    pub id: [u8; 4],
+    /// Index of the chunk among all chunks of the stream.
+    pub index: usize,
+    /// Index among the stream's tracks, for `"TRK "` chunks.
+    pub track: Option<usize>,
+    /// Offset of the chunk's ID in the stream.
+    pub offset: u64,
+    /// Declared length of the chunk data.
+    pub len: u32,
+}
+
+/// Streaming decoder for TSQ1 data.
+///
+/// ```no_run
+/// # fn main() -> Result<(), tsq1::Error> {
+/// let file = std::io::BufReader::new(std::fs::File::open("capture.tsq")?);
+/// let mut reader = tsq1::Reader::new(file)?;
+/// while let Some(chunk) = reader.next_chunk()? {
+///     if &chunk.id == b"TRK " {
+///         while let Some(event) = reader.next_event()? {
+///             println!("{event:?}");
+///         }
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Whatever is left of a chunk when the next one is requested is skipped,
+/// by seeking for readers created with [`Reader::new_seekable`] and by
+/// reading and discarding it otherwise. Events are validated like
+/// [`read`](crate::read) does; custom payloads stay undecoded.
+pub struct Reader<R> {
+    inner: R,
+    header: Header,
+    skip: fn(&mut R, u64) -> io::Result<()>,
+    /// Offset of the next byte of `inner`.
+    offset: u64,
+    current: Option<ChunkHeader>,
+    /// Chunks and tracks announced so far.
+    chunks: usize,
+    tracks: usize,
+    /// Events decoded from the current chunk.
+    events: usize,
+    /// Bytes of the current chunk not consumed yet.
+    remaining: u64,
+    /// Bytes of the event being decoded.
+    event: Vec<u8>,
+}
+
+impl<R: Read> Reader<R> {
+    /// Read the file header from `inner`. Skipped chunk data is read and
+    /// discarded.
+    pub fn new(inner: R) -> Result<Self, Error> {
+        Reader::with_skip(inner, discard)
+    }
+}
+
+impl<R: Read + Seek> Reader<R> {
+    /// Like [`Reader::new`], but skipped chunk data is seeked over.
+    ///
+    /// Seeking cannot tell whether a skipped chunk is complete, so a
+    /// truncated last chunk goes unnoticed if it is skipped.
+    pub fn new_seekable(inner: R) -> Result<Self, Error> {
+        Reader::with_skip(inner, |inner, len| {
+            let len = i64::try_from(len).map_err(|_| io::ErrorKind::InvalidInput)?;
+            inner.seek(SeekFrom::Current(len)).map(|_| ())
+        })
+    }
+}
+
+impl<R: Read> Reader<R> {
+    fn with_skip(mut inner: R, skip: fn(&mut R, u64) -> io::Result<()>) -> Result<Self, Error> {
+        let mut bytes = [0u8; HEADER_SIZE];
+        let len = read_up_to(&mut inner, &mut bytes)?;
+        let header = parse_header(&bytes[..len])?;
+        Ok(Reader {
+            inner,
+            header,
+            skip,
+            offset: HEADER_SIZE as u64,
+            current: None,
+            chunks: 0,
+            tracks: 0,
+            events: 0,
+            remaining: 0,
+            event: Vec::new(),
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// The chunk being read, if any.
+    pub fn current_chunk(&self) -> Option<ChunkHeader> {
+        self.current
+    }
+
+    /// Offset of the next byte to be read.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Skip the rest of the current chunk and read the next chunk header.
+    /// Returns `None` at the end of the stream.
+    pub fn next_chunk(&mut self) -> Result<Option<ChunkHeader>, Error> {
+        self.skip_chunk()?;
+        let mut head = [0u8; 8];
+        match read_up_to(&mut self.inner, &mut head)? {
+            0 => return Ok(None),
+            8 => {}
+            _ => {
+                let mut err = self.error(Error::Invalid("TSQ chunk header truncated"));
+                err.chunk_index = Some(self.chunks);
+                return Err(err.into());
+            }
+        }
+        let id = [head[0], head[1], head[2], head[3]];
+        let chunk = ChunkHeader {
+            id,
+            index: self.chunks,
+            track: (&id == b"TRK ").then_some(self.tracks),
+            offset: self.offset,
+            len: u32::from_le_bytes([head[4], head[5], head[6], head[7]]),
+        };
+        self.chunks += 1;
+        self.tracks += chunk.track.is_some() as usize;
+        self.offset += 8;
+        self.current = Some(chunk);
+        self.events = 0;
+        self.remaining = chunk.len as u64;
+        Ok(Some(chunk))
+    }
+
+    /// Skip whatever is left of the current chunk.
+    pub fn skip_chunk(&mut self) -> Result<(), Error> {
+        if self.remaining > 0 {
+            if let Err(err) = (self.skip)(&mut self.inner, self.remaining) {
+                return Err(match err.kind() {
+                    io::ErrorKind::UnexpectedEof => self.truncated(),
+                    _ => Error::Io(err),
+                });
+            }
+            self.offset += self.remaining;
+            self.remaining = 0;
+        }
+        self.current = None;
+        Ok(())
+    }
+
+    /// Decode the next event of the current `"TRK "` chunk, or `None` once
+    /// the chunk is exhausted.
+    pub fn next_event(&mut self) -> Result<Option<Event>, Error> {
+        match self.current {
+            Some(chunk) if &chunk.id == b"TRK " => {}
+            _ => return Err(Error::Invalid("no track chunk is being read")),
+        }
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        let start = self.offset;
+        self.event.clear();
+        self.fill_event()?;
+        let mut events =
+            EventIter::new(&self.event, self.header.flags).with_base_offset(start as usize);
+        let event = match events.next() {
+            Some(Ok(event)) => event.to_event(),
+            Some(Err(err)) => return Err(self.locate(err).into()),
+            None => return Ok(None),
+        };
+        if let EventKind::Osc(osc) = &event.kind {
+            osc.validate()
+                .map_err(|err| self.locate(DecodeError::new(start as usize, err)))?;
+        }
+        self.events += 1;
+        Ok(Some(event))
+    }
+
+    /// Decode the rest of the current `"TRK "` chunk.
+    pub fn read_track(&mut self) -> Result<Track, Error> {
+        let mut track = Track::new();
+        while let Some(event) = self.next_event()? {
+            track.push(event);
+        }
+        Ok(track)
+    }
+
+    /// Read and decode the current non-track chunk, which must not have
+    /// been partially consumed.
+    pub fn read_chunk(&mut self) -> Result<Chunk, Error> {
+        let chunk = match self.current {
+            Some(chunk) if &chunk.id != b"TRK " && self.remaining == chunk.len as u64 => chunk,
+            _ => return Err(Error::Invalid("no unread non-track chunk is being read")),
+        };
+        let mut data = Vec::new();
+        self.take(chunk.len as u64, &mut data)?;
+        read_chunk(chunk.id, &data).map_err(|err| {
+            let err = DecodeError::new(chunk.offset as usize + 8, err);
+            err.in_chunk(chunk.id, chunk.index, chunk.track).into()
+        })
+    }
+
+    /// Copy the bytes of one event into `self.event`, stopping early at the
+    /// end of the chunk; decoding then reports where the event broke off.
+    fn fill_event(&mut self) -> Result<(), Error> {
+        let mut buf = core::mem::take(&mut self.event);
+        let result = self.fill_event_into(&mut buf);
+        self.event = buf;
+        result
+    }
+
+    fn fill_event_into(&mut self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        if !self.take(1, buf)? {
+            return Ok(());
+        }
+        let kind = buf[0] & 0x7F;
+        if self.take_vlq(buf)?.is_none() {
+            return Ok(());
+        }
+        let len = match kind {
+            EK_OSC | EK_META | EK_CUSTOM => {
+                if !self.take(1, buf)? {
+                    return Ok(());
+                }
+                self.take_vlq(buf)?
+            }
+            EK_SYSEX => self.take_vlq(buf)?,
+            EK_MIDI => {
+                if !self.take(1, buf)? {
+                    return Ok(());
+                }
+                let single = matches!(buf[buf.len() - 1] >> 4, 0xC | 0xD);
+                Some(if single && !self.header.midi_three_bytes() {
+                    1
+                } else {
+                    2
+                })
+            }
+            _ => None,
+        };
+        if let Some(len) = len {
+            self.take(len, buf)?;
+        }
+        Ok(())
+    }
+
+    /// Append a VLQ to `buf` and return its value, or `None` if the chunk
+    /// ends first.
+    fn take_vlq(&mut self, buf: &mut Vec<u8>) -> Result<Option<u64>, Error> {
+        let mut value = 0u64;
+        for _ in 0..10 {
+            if !self.take(1, buf)? {
+                return Ok(None);
+            }
+            let byte = buf[buf.len() - 1];
+            value = (value << 7) | (byte & 0x7F) as u64;
+            if byte & 0x80 == 0 {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Append up to `len` bytes of the current chunk to `buf`; returns
+    /// whether all of them were available.
+    fn take(&mut self, len: u64, buf: &mut Vec<u8>) -> Result<bool, Error> {
+        let wanted = len.min(self.remaining);
+        let read = (&mut self.inner).take(wanted).read_to_end(buf)? as u64;
+        self.offset += read;
+        self.remaining -= read;
+        if read < wanted {
+            return Err(self.truncated());
+        }
+        Ok(wanted == len)
+    }
+
+    /// `error` at the current offset.
+    fn error(&self, error: Error) -> DecodeError {
+        DecodeError::new(self.offset as usize, error)
+    }
+
+    /// Attribute `err` to the current chunk and, in a track, the event
+    /// being decoded.
+    fn locate(&self, err: DecodeError) -> DecodeError {
+        match self.current {
+            Some(chunk) => {
+                let err = err.in_chunk(chunk.id, chunk.index, chunk.track);
+                match chunk.track {
+                    Some(_) => err.at_event(self.events),
+                    None => err,
+                }
+            }
+            None => err,
+        }
+    }
+
+    /// The stream ended before the current chunk did.
+    fn truncated(&self) -> Error {
+        let err = self.error(Error::Invalid("TSQ chunk length exceeds remaining data"));
+        self.locate(err).into()
+    }
+}
+
+fn discard<R: Read>(inner: &mut R, len: u64) -> io::Result<()> {
+    let skipped = io::copy(&mut inner.take(len), &mut io::sink())?;
+    if skipped == len {
+        Ok(())
+    } else {
+        Err(io::ErrorKind::UnexpectedEof.into())
+    }
+}
+
+/// Fill as much of `buf` as the stream provides; returns the bytes read.
+fn read_up_to<R: Read>(inner: &mut R, buf: &mut [u8]) -> Result<usize, Error> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match inner.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(Error::Io(err)),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{MetaEvent, MidiEvent};
+    use crate::sequence::{RawChunk, Sequence};
+    use crate::tempo::{TempoEntry, TempoMap};
+    use crate::ErrorKind;
+    use alloc::vec;
+    use std::io::Cursor;
+
+    /// A reader that cannot seek, like a pipe.
+    struct Pipe<'a>(&'a [u8]);
+
+    impl Read for Pipe<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            // Hand out a few bytes at a time to exercise short reads.
+            let len = buf.len().min(self.0.len()).min(3);
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    fn sample() -> Sequence {
+        let mut seq = Sequence::new(480);
+        seq.header.flags = crate::FLAG_MIDI_THREE_BYTES;
+        seq.tracks.push(Track::with_events(vec![
+            Event::musical(0, EventKind::Midi(MidiEvent::new(0xC1, 5, 0))),
+            Event::absolute(1_000, EventKind::SysEx(vec![0xF0, 0x7E, 0xF7])),
+            Event::musical(480, EventKind::Meta(MetaEvent::new(0x2F, vec![]))),
+        ]));
+        seq.tracks.push(Track::with_events(vec![Event::musical(
+            960,
+            EventKind::Midi(MidiEvent::new(0x90, 60, 100)),
+        )]));
+        seq.chunks
+            .push(Chunk::TempoMap(TempoMap::from_entries(vec![TempoEntry {
+                tick: 0,
+                us_per_qn: 500_000,
+            }])));
+        seq.chunks.push(Chunk::Unknown(RawChunk {
+            id: *b"XTRA",
+            data: vec![0xAA; 100],
+        }));
+        seq
+    }
+
+    fn stream<R: Read>(mut reader: Reader<R>) -> Result<Sequence, Error> {
+        let mut seq = Sequence::new(0);
+        seq.header = reader.header().clone();
+        while let Some(chunk) = reader.next_chunk()? {
+            match &chunk.id {
+                b"TRK " => seq.tracks.push(reader.read_track()?),
+                b"TMAP" => seq.chunks.push(reader.read_chunk()?),
+                _ => {}
+            }
+        }
+        Ok(seq)
+    }
+
+    #[test]
+    fn streams_tracks_and_skips_chunks() {
+        let mut expected = sample();
+        let bytes = expected.to_vec().unwrap();
+        expected.header.track_count = 2;
+        expected.chunks.pop();
+
+        let piped = stream(Reader::new(Pipe(&bytes)).unwrap()).unwrap();
+        assert_eq!(piped, expected);
+        let mut reader = Reader::new_seekable(Cursor::new(&bytes)).unwrap();
+        assert_eq!(
+            reader.next_chunk().unwrap().map(|chunk| chunk.id),
+            Some(*b"TRK ")
+        );
+        assert_eq!(stream(reader).unwrap().tracks, expected.tracks[1..]);
+    }
+
+    #[test]
+    fn reports_truncation_and_decoding_errors() {
+        let bytes = sample().to_vec().unwrap();
+        // Cut inside the second event of the first track.
+        let truncated = &bytes[..HEADER_SIZE + 8 + 6];
+        let mut reader = Reader::new(Pipe(truncated)).unwrap();
+        reader.next_chunk().unwrap();
+        assert!(reader.next_event().unwrap().is_some());
+        let err = reader.next_event().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Invalid);
+        assert_eq!(err.location().unwrap().event, Some(1));
+
+        // A chunk whose declared length hides half of an event.
+        let mut seq = Sequence::new(480);
+        seq.tracks.push(Track::with_events(vec![Event::musical(
+            0,
+            EventKind::Midi(MidiEvent::new(0x90, 60, 100)),
+        )]));
+        let mut bytes = seq.to_vec().unwrap();
+        bytes[HEADER_SIZE + 4] = 2;
+        bytes.truncate(HEADER_SIZE + 8 + 2);
+        let mut reader = Reader::new(Pipe(&bytes)).unwrap();
+        reader.next_chunk().unwrap();
+        let err = reader.next_event().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Invalid);
+        let at = err.location().unwrap();
+        assert_eq!(
+            (at.offset, at.chunk_index, at.track),
+            (HEADER_SIZE + 8 + 2, Some(0), Some(0))
+        );
+        assert!(matches!(reader.read_chunk(), Err(Error::Invalid(_))));
+
+        assert!(Reader::new(Pipe(b"TSQ1")).is_err());
+    }
+}
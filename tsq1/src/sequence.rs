@@ -0,0 +1,470 @@
+//! Owned representation of a complete TSQ1 file.
+
+use alloc::collections::BinaryHeap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+
+use crate::event::{Domain, Event, EventKind, EventTime};
+use crate::marker::Locator;
+use crate::sync::SyncMap;
+use crate::tempo::TempoMap;
+use crate::Error;
+
+/// Magic bytes at the start of every TSQ1 file.
+pub const MAGIC: [u8; 4] = *b"TSQ1";
+/// Format version written by this crate.
+pub const VERSION: u16 = 1;
+/// Size of the fixed file header in bytes.
+pub const HEADER_SIZE: usize = 14;
+
+/// `Flags` bit: SysEx payloads include their leading `0xF0` / `0xF7` byte.
+pub const FLAG_SYSEX_STATUS_IN_PAYLOAD: u16 = 0x0001;
+/// `Flags` bit: every MIDI event stores three bytes as spec §4.2 requires,
+/// padding program change and channel aftertouch with a trailing byte.
+/// Without it those messages store only two bytes (the legacy layout).
+pub const FLAG_MIDI_THREE_BYTES: u16 = 0x0002;
+/// `Flags` bit: tracks are independent patterns (SMF Format 2), each with
+/// its own timeline starting at zero, rather than parts played together.
+pub const FLAG_SEQUENTIAL_TRACKS: u16 = 0x0004;
+
+/// Unit used for absolute-domain deltas and positions (`AbsUnit`).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum AbsUnit {
+    /// Microseconds (`0`).
+    #[default]
+    Microseconds,
+    /// Nanoseconds (`1`).
+    Nanoseconds,
+}
+
+impl AbsUnit {
+    /// Interpret an `AbsUnit` header byte.
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(AbsUnit::Microseconds),
+            1 => Some(AbsUnit::Nanoseconds),
+            _ => None,
+        }
+    }
+
+    /// The encoded `AbsUnit` header byte.
+    pub fn as_u8(self) -> u8 {
+        match self {
+            AbsUnit::Microseconds => 0,
+            AbsUnit::Nanoseconds => 1,
+        }
+    }
+
+    /// Number of nanoseconds in one unit.
+    pub fn nanos_per_unit(self) -> u64 {
+        match self {
+            AbsUnit::Microseconds => 1_000,
+            AbsUnit::Nanoseconds => 1,
+        }
+    }
+
+    /// Express `value` units in nanoseconds.
+    pub fn to_nanos(self, value: u64) -> u128 {
+        value as u128 * self.nanos_per_unit() as u128
+    }
+}
+
+/// TSQ1 file header (spec §1).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub version: u16,
+    /// Ticks per quarter note.
+    pub ppq: u16,
+    pub abs_unit: AbsUnit,
+    /// Reserved byte; must be 0 but is kept verbatim when reading.
+    pub reserved: u8,
+    /// Advisory track count as stored in the file.
+    pub track_count: u16,
+    pub flags: u16,
+}
+
+impl Header {
+    /// Header for a version 1 file with the given PPQ, microsecond AbsUnit
+    /// and the three-byte MIDI layout.
+    pub fn new(ppq: u16) -> Self {
+        Header {
+            version: VERSION,
+            ppq,
+            abs_unit: AbsUnit::Microseconds,
+            reserved: 0,
+            track_count: 0,
+            flags: FLAG_MIDI_THREE_BYTES,
+        }
+    }
+
+    /// Whether MIDI events use the three-byte layout
+    /// ([`FLAG_MIDI_THREE_BYTES`]).
+    pub fn midi_three_bytes(&self) -> bool {
+        self.flags & FLAG_MIDI_THREE_BYTES != 0
+    }
+
+    /// Whether tracks are independent patterns
+    /// ([`FLAG_SEQUENTIAL_TRACKS`]).
+    pub fn sequential_tracks(&self) -> bool {
+        self.flags & FLAG_SEQUENTIAL_TRACKS != 0
+    }
+}
+
+/// Event stream stored in a `"TRK "` chunk.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Track {
+    pub events: Vec<Event>,
+}
+
+impl Track {
+    pub fn new() -> Self {
+        Track::default()
+    }
+
+    pub fn with_events(events: Vec<Event>) -> Self {
+        Track { events }
+    }
+
+    pub fn push(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    /// Build a track from events at cumulative positions.
+    ///
+    /// Events keep the given order. Positions must not decrease within each
+    /// domain; musical and absolute events may be interleaved freely.
+    pub fn from_timed<I>(events: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = (EventTime, EventKind)>,
+    {
+        let mut clocks = [0u64; 2];
+        let mut track = Track::new();
+        for (time, kind) in events {
+            let clock = &mut clocks[domain_index(time.domain())];
+            let delta = time.value().checked_sub(*clock).ok_or(Error::Invalid(
+                "event positions must not decrease within a domain",
+            ))?;
+            *clock = time.value();
+            track.push(Event {
+                domain: time.domain(),
+                delta,
+                kind,
+            });
+        }
+        Ok(track)
+    }
+
+    /// Iterate over events together with their cumulative position.
+    ///
+    /// Each domain keeps its own clock: a musical delta advances only the
+    /// tick position and an absolute delta only the `AbsUnit` position.
+    /// Positions saturate at `u64::MAX`.
+    pub fn timed_events(&self) -> impl Iterator<Item = (EventTime, &Event)> + '_ {
+        let mut clocks = [0u64; 2];
+        self.events.iter().map(move |event| {
+            let clock = &mut clocks[domain_index(event.domain)];
+            *clock = clock.saturating_add(event.delta);
+            let time = match event.domain {
+                Domain::Musical => EventTime::Musical(*clock),
+                Domain::Absolute => EventTime::Absolute(*clock),
+            };
+            (time, event)
+        })
+    }
+}
+
+pub(crate) fn domain_index(domain: Domain) -> usize {
+    match domain {
+        Domain::Musical => 0,
+        Domain::Absolute => 1,
+    }
+}
+
+/// A chunk with an ID this crate does not interpret, kept verbatim.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawChunk {
+    pub id: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+/// Any non-`"TRK "` chunk of a sequence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Chunk {
+    /// `"TMAP"` tempo map.
+    TempoMap(TempoMap),
+    /// `"SYNC"` absolute anchors.
+    Sync(SyncMap),
+    /// `"MARK"` locators, sorted by `pos` within each `pos_kind`.
+    Markers(Vec<Locator>),
+    /// Any other chunk ID.
+    Unknown(RawChunk),
+}
+
+impl Chunk {
+    /// The four-byte chunk ID this chunk is stored under.
+    pub fn id(&self) -> [u8; 4] {
+        match self {
+            Chunk::TempoMap(_) => *b"TMAP",
+            Chunk::Sync(_) => *b"SYNC",
+            Chunk::Markers(_) => *b"MARK",
+            Chunk::Unknown(raw) => raw.id,
+        }
+    }
+
+    /// Place of the chunk in the canonical layout, after every track.
+    pub(crate) fn rank(&self) -> u8 {
+        match self {
+            Chunk::TempoMap(_) => 0,
+            Chunk::Sync(_) => 1,
+            Chunk::Markers(_) => 2,
+            Chunk::Unknown(_) => 3,
+        }
+    }
+}
+
+/// Chunk order of a file read by [`crate::read`] whose layout or
+/// `TrackCount` was not canonical.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Layout {
+    /// Chunk IDs in file order, `"TRK "` included.
+    ids: Vec<[u8; 4]>,
+}
+
+impl Layout {
+    /// Layout of a file with the chunk `ids` that decoded to `seq`, or
+    /// `None` if writing `seq` canonically reproduces it.
+    pub(crate) fn recorded(ids: Vec<[u8; 4]>, seq: &Sequence) -> Option<Layout> {
+        let tracks = seq.tracks.len();
+        let canonical = seq.header.track_count as usize == tracks
+            && ids[..tracks].iter().all(|id| id == b"TRK ")
+            && seq.chunks.is_sorted_by_key(Chunk::rank);
+        (!canonical).then_some(Layout { ids })
+    }
+
+    /// Chunk IDs in file order, if they still describe `seq`: the same
+    /// number of tracks and the same chunk IDs in the same order.
+    pub(crate) fn ids_for(&self, seq: &Sequence) -> Option<&[[u8; 4]]> {
+        let tracks = self.ids.iter().filter(|id| *id == b"TRK ").count();
+        let chunks = self.ids.iter().copied().filter(|id| id != b"TRK ");
+        let fits = tracks == seq.tracks.len() && chunks.eq(seq.chunks.iter().map(Chunk::id));
+        fits.then_some(&self.ids)
+    }
+}
+
+/// A complete TSQ1 sequence: header, event tracks and auxiliary chunks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sequence {
+    pub header: Header,
+    pub tracks: Vec<Track>,
+    /// Non-track chunks in file order.
+    pub chunks: Vec<Chunk>,
+    /// How the file this was read from ordered its chunks, if not
+    /// canonically; see [`Sequence::to_vec`].
+    pub(crate) layout: Option<Layout>,
+}
+
+impl Sequence {
+    /// Empty sequence with the given PPQ.
+    pub fn new(ppq: u16) -> Self {
+        Sequence {
+            header: Header::new(ppq),
+            tracks: Vec::new(),
+            chunks: Vec::new(),
+            layout: None,
+        }
+    }
+
+    /// Split the sequence into one single-track sequence per track.
+    ///
+    /// This is how the patterns of a [sequential](Header::sequential_tracks)
+    /// file are used independently, e.g. to build a
+    /// [`Timeline`](crate::Timeline) for each. Every pattern gets a copy of
+    /// the auxiliary chunks and the header without [`FLAG_SEQUENTIAL_TRACKS`].
+    pub fn patterns(&self) -> Vec<Sequence> {
+        self.tracks
+            .iter()
+            .map(|track| Sequence {
+                header: Header {
+                    track_count: 1,
+                    flags: self.header.flags & !FLAG_SEQUENTIAL_TRACKS,
+                    ..self.header.clone()
+                },
+                tracks: vec![track.clone()],
+                chunks: self.chunks.clone(),
+                layout: None,
+            })
+            .collect()
+    }
+
+    /// Events of `domain` from every track, merged in playback order.
+    ///
+    /// Yields `(track_index, position, event)`, where `position` is the
+    /// event's tick or `AbsUnit` position. Ties go to the lower track index
+    /// and then keep the order within the track. Events of the other domain
+    /// are skipped; use [`Timeline::merged_events`](crate::Timeline::merged_events)
+    /// to order both domains together.
+    pub fn merged(&self, domain: Domain) -> MergedEvents<'_> {
+        let mut merged = MergedEvents {
+            domain,
+            tracks: self
+                .tracks
+                .iter()
+                .map(|track| (track.events.iter(), 0, None))
+                .collect(),
+            heads: BinaryHeap::with_capacity(self.tracks.len()),
+        };
+        for track in 0..merged.tracks.len() {
+            merged.advance(track);
+        }
+        merged
+    }
+}
+
+/// Iterator returned by [`Sequence::merged`].
+pub struct MergedEvents<'a> {
+    domain: Domain,
+    /// Remaining events, clock and queued event of each track.
+    tracks: Vec<(core::slice::Iter<'a, Event>, u64, Option<&'a Event>)>,
+    /// Position and index of every track with a queued event.
+    heads: BinaryHeap<Reverse<(u64, usize)>>,
+}
+
+impl<'a> MergedEvents<'a> {
+    /// Queue the next event of `track` in the merged domain, if any.
+    fn advance(&mut self, track: usize) {
+        let (events, clock, queued) = &mut self.tracks[track];
+        let domain = self.domain;
+        *queued = events.find(|event| event.domain == domain);
+        if let Some(event) = queued {
+            *clock = clock.saturating_add(event.delta);
+            self.heads.push(Reverse((*clock, track)));
+        }
+    }
+}
+
+impl<'a> Iterator for MergedEvents<'a> {
+    type Item = (usize, u64, &'a Event);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((position, track)) = self.heads.pop()?;
+        let event = self.tracks[track].2?;
+        self.advance(track);
+        Some((track, position, event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::MidiEvent;
+
+    fn note(key: u8) -> EventKind {
+        EventKind::Midi(MidiEvent::new(0x90, key, 100))
+    }
+
+    #[test]
+    fn mixed_domains_keep_separate_clocks() {
+        let track = Track::with_events(vec![
+            Event::musical(480, note(60)),
+            Event::absolute(1_000, note(61)),
+            Event::musical(240, note(62)),
+            Event::absolute(0, note(63)),
+            Event::absolute(500, note(64)),
+        ]);
+        let times: Vec<EventTime> = track.timed_events().map(|(time, _)| time).collect();
+        assert_eq!(
+            times,
+            vec![
+                EventTime::Musical(480),
+                EventTime::Absolute(1_000),
+                EventTime::Musical(720),
+                EventTime::Absolute(1_000),
+                EventTime::Absolute(1_500),
+            ]
+        );
+
+        let rebuilt = Track::from_timed(
+            track
+                .timed_events()
+                .map(|(time, event)| (time, event.kind.clone())),
+        )
+        .unwrap();
+        assert_eq!(rebuilt, track);
+    }
+
+    #[test]
+    fn merged_orders_each_domain_across_tracks() {
+        let mut seq = Sequence::new(480);
+        seq.tracks.push(Track::with_events(vec![
+            Event::musical(480, note(60)),
+            Event::absolute(2_000, note(61)),
+            Event::musical(0, note(62)),
+        ]));
+        seq.tracks.push(Track::with_events(vec![
+            Event::musical(0, note(70)),
+            Event::musical(480, note(71)),
+            Event::absolute(1_000, note(72)),
+        ]));
+        seq.tracks.push(Track::new());
+
+        let key = |event: &Event| match &event.kind {
+            EventKind::Midi(midi) => midi.data1,
+            _ => unreachable!(),
+        };
+        let musical: Vec<(usize, u64, u8)> = seq
+            .merged(Domain::Musical)
+            .map(|(track, tick, event)| (track, tick, key(event)))
+            .collect();
+        assert_eq!(
+            musical,
+            vec![(1, 0, 70), (0, 480, 60), (0, 480, 62), (1, 480, 71)]
+        );
+        let absolute: Vec<(usize, u64, u8)> = seq
+            .merged(Domain::Absolute)
+            .map(|(track, time, event)| (track, time, key(event)))
+            .collect();
+        assert_eq!(absolute, vec![(1, 1_000, 72), (0, 2_000, 61)]);
+    }
+
+    #[test]
+    fn from_timed_rejects_decreasing_positions() {
+        let result = Track::from_timed(vec![
+            (EventTime::Absolute(10), note(60)),
+            (EventTime::Musical(0), note(61)),
+            (EventTime::Absolute(5), note(62)),
+        ]);
+        assert!(matches!(result, Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn patterns_split_sequential_tracks() {
+        let mut seq = Sequence::new(96);
+        seq.header.flags = FLAG_SEQUENTIAL_TRACKS | FLAG_MIDI_THREE_BYTES;
+        seq.header.track_count = 2;
+        seq.tracks = vec![
+            Track::with_events(vec![Event::musical(96, note(60))]),
+            Track::with_events(vec![Event::musical(48, note(62))]),
+        ];
+        seq.chunks
+            .push(Chunk::TempoMap(TempoMap::from_entries(Vec::new())));
+        assert!(seq.header.sequential_tracks());
+
+        let patterns = seq.patterns();
+        assert_eq!(patterns.len(), 2);
+        for (pattern, track) in patterns.iter().zip(&seq.tracks) {
+            assert!(!pattern.header.sequential_tracks());
+            assert!(pattern.header.midi_three_bytes());
+            assert_eq!(pattern.header.track_count, 1);
+            assert_eq!(pattern.tracks.len(), 1);
+            assert_eq!(&pattern.tracks[0], track);
+            assert_eq!(pattern.chunks, seq.chunks);
+        }
+    }
+
+    #[test]
+    fn abs_unit_scales_to_nanoseconds() {
+        assert_eq!(AbsUnit::Microseconds.to_nanos(150_000), 150_000_000);
+        assert_eq!(AbsUnit::Nanoseconds.to_nanos(u64::MAX), u64::MAX as u128);
+    }
+}
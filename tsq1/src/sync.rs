@@ -0,0 +1,175 @@
+//! `"SYNC"` absolute anchors (spec §5).
+
+use alloc::vec::Vec;
+
+use crate::sequence::AbsUnit;
+
+/// One `(tick, time_abs)` anchor of a `"SYNC"` chunk.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SyncAnchor {
+    pub tick: u64,
+    /// Absolute position in the sequence's `AbsUnit`.
+    pub time_abs: u64,
+}
+
+/// Anchors pinning musical positions to absolute time, stored in a `"SYNC"`
+/// chunk.
+///
+/// Between two anchors ticks and absolute time are related linearly; before
+/// the first and after the last anchor the nearest segment is extended.
+/// Conversions need two anchors at distinct positions on the source axis and
+/// round towards the earlier position. Entries are kept in the order they
+/// were read so files round-trip unchanged; lookups assume the map is
+/// [monotonic](SyncMap::is_monotonic).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SyncMap {
+    pub anchors: Vec<SyncAnchor>,
+}
+
+impl SyncMap {
+    pub fn new() -> Self {
+        SyncMap::default()
+    }
+
+    pub fn from_anchors(anchors: Vec<SyncAnchor>) -> Self {
+        SyncMap { anchors }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchors.is_empty()
+    }
+
+    /// Insert an anchor after any existing anchors at the same or an earlier tick.
+    pub fn insert(&mut self, anchor: SyncAnchor) {
+        let index = self.anchors.partition_point(|a| a.tick <= anchor.tick);
+        self.anchors.insert(index, anchor);
+    }
+
+    /// Whether anchors are in non-decreasing order of both tick and time.
+    pub fn is_monotonic(&self) -> bool {
+        self.anchors
+            .windows(2)
+            .all(|w| w[0].tick <= w[1].tick && w[0].time_abs <= w[1].time_abs)
+    }
+
+    /// Absolute position (in the map's `AbsUnit`) of `tick`.
+    ///
+    /// Returns `None` without two anchors at distinct ticks or when the
+    /// result falls outside `0..=u64::MAX`.
+    pub fn tick_to_abs(&self, tick: u64) -> Option<u64> {
+        let value = self.interpolate(tick as u128, |a| a.tick as u128, |a| a.time_abs as u128)?;
+        u64::try_from(value).ok()
+    }
+
+    /// Tick position of `time_abs` (in the map's `AbsUnit`).
+    pub fn abs_to_tick(&self, time_abs: u64) -> Option<u64> {
+        let value =
+            self.interpolate(time_abs as u128, |a| a.time_abs as u128, |a| a.tick as u128)?;
+        u64::try_from(value).ok()
+    }
+
+    /// Position of `tick` in nanoseconds, given the map's `AbsUnit`.
+    pub fn tick_to_nanos(&self, tick: u64, unit: AbsUnit) -> Option<u128> {
+        let value = self.interpolate(
+            tick as u128,
+            |a| a.tick as u128,
+            |a| unit.to_nanos(a.time_abs),
+        )?;
+        u128::try_from(value).ok()
+    }
+
+    /// Tick position of `nanos`, given the map's `AbsUnit`.
+    ///
+    /// Unlike converting `nanos` to `unit` first, this keeps sub-unit
+    /// precision in the interpolation.
+    pub fn nanos_to_tick(&self, nanos: u128, unit: AbsUnit) -> Option<u64> {
+        let value = self.interpolate(nanos, |a| unit.to_nanos(a.time_abs), |a| a.tick as u128)?;
+        u64::try_from(value).ok()
+    }
+
+    /// Map `pos` from the axis given by `key` onto the axis given by `value`.
+    ///
+    /// Interpolates between the last anchor at or before `pos` and the next
+    /// anchor further along the axis, so at a position shared by several
+    /// anchors the last of them applies. Outside the anchors the first or
+    /// last segment is extended.
+    fn interpolate(
+        &self,
+        pos: u128,
+        key: impl Fn(&SyncAnchor) -> u128,
+        value: impl Fn(&SyncAnchor) -> u128,
+    ) -> Option<i128> {
+        let anchors = &self.anchors;
+        let split = anchors.partition_point(|a| key(a) <= pos);
+        let (lower, upper) = if split == anchors.len() {
+            let upper = anchors.last()?;
+            let lower = anchors.iter().rev().find(|a| key(a) < key(upper))?;
+            (lower, upper)
+        } else if split == 0 {
+            let lower = &anchors[0];
+            let upper = anchors.iter().find(|a| key(a) > key(lower))?;
+            (lower, upper)
+        } else {
+            (&anchors[split - 1], &anchors[split])
+        };
+        let (x0, x1) = (
+            i128::try_from(key(lower)).ok()?,
+            i128::try_from(key(upper)).ok()?,
+        );
+        let (y0, y1) = (
+            i128::try_from(value(lower)).ok()?,
+            i128::try_from(value(upper)).ok()?,
+        );
+        let offset = (i128::try_from(pos).ok()? - x0).checked_mul(y1 - y0)?;
+        Some(y0 + offset.div_euclid(x1 - x0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn anchor(tick: u64, time_abs: u64) -> SyncAnchor {
+        SyncAnchor { tick, time_abs }
+    }
+
+    #[test]
+    fn interpolates_and_extends_segments() {
+        // 480 ticks per 500 ms, then 480 ticks per 250 ms.
+        let map = SyncMap::from_anchors(vec![
+            anchor(480, 500_000),
+            anchor(960, 1_000_000),
+            anchor(1440, 1_250_000),
+        ]);
+        assert_eq!(map.tick_to_abs(0), Some(0));
+        assert_eq!(map.tick_to_abs(720), Some(750_000));
+        assert_eq!(map.tick_to_abs(1200), Some(1_125_000));
+        assert_eq!(map.tick_to_abs(1920), Some(1_500_000));
+        assert_eq!(map.abs_to_tick(750_000), Some(720));
+        assert_eq!(map.abs_to_tick(1_125_000), Some(1200));
+        // Rounds towards the earlier position.
+        assert_eq!(map.abs_to_tick(1_001), Some(0));
+        assert_eq!(map.tick_to_abs(1), Some(1_041));
+    }
+
+    #[test]
+    fn nanosecond_conversions_keep_sub_unit_precision() {
+        let map = SyncMap::from_anchors(vec![anchor(0, 0), anchor(3, 1)]);
+        assert_eq!(map.tick_to_nanos(1, AbsUnit::Microseconds), Some(333));
+        assert_eq!(map.tick_to_abs(1), Some(0));
+        assert_eq!(map.nanos_to_tick(667, AbsUnit::Microseconds), Some(2));
+        assert_eq!(map.nanos_to_tick(667, AbsUnit::Nanoseconds), Some(2_001));
+    }
+
+    #[test]
+    fn degenerate_maps_do_not_convert() {
+        assert_eq!(SyncMap::new().tick_to_abs(0), None);
+        let single = SyncMap::from_anchors(vec![anchor(0, 0), anchor(0, 10)]);
+        assert_eq!(single.tick_to_abs(0), None);
+        // Extending backwards past zero has no position.
+        let late = SyncMap::from_anchors(vec![anchor(0, 1_000), anchor(10, 2_000)]);
+        assert_eq!(late.abs_to_tick(0), None);
+        assert!(late.is_monotonic());
+    }
+}
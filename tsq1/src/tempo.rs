@@ -0,0 +1,91 @@
+//! `"TMAP"` tempo map (spec §2).
+
+use alloc::vec::Vec;
+
+/// Tempo assumed before the first tempo map entry (120 BPM), as in SMF.
+pub const DEFAULT_US_PER_QN: u32 = 500_000;
+
+/// One `(tick, us_per_qn)` entry of a `"TMAP"` chunk.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TempoEntry {
+    pub tick: u64,
+    /// Microseconds per quarter note from `tick` onwards.
+    pub us_per_qn: u32,
+}
+
+/// Tempo changes of a sequence, stored in a `"TMAP"` chunk.
+///
+/// Entries are kept in the order they were read so files round-trip
+/// unchanged; [`TempoMap::insert`] keeps a sorted map sorted.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TempoMap {
+    pub entries: Vec<TempoEntry>,
+}
+
+impl TempoMap {
+    pub fn new() -> Self {
+        TempoMap::default()
+    }
+
+    pub fn from_entries(entries: Vec<TempoEntry>) -> Self {
+        TempoMap { entries }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Insert an entry after any existing entries at the same or an earlier tick.
+    pub fn insert(&mut self, entry: TempoEntry) {
+        let index = self.entries.partition_point(|e| e.tick <= entry.tick);
+        self.entries.insert(index, entry);
+    }
+
+    /// Whether entries are in non-decreasing tick order.
+    pub fn is_sorted(&self) -> bool {
+        self.entries.windows(2).all(|w| w[0].tick <= w[1].tick)
+    }
+
+    /// Tempo in effect at `tick`.
+    ///
+    /// The last entry at or before `tick` wins; before the first entry the
+    /// SMF default of [`DEFAULT_US_PER_QN`] applies. Assumes a sorted map.
+    pub fn tempo_at(&self, tick: u64) -> u32 {
+        let index = self.entries.partition_point(|e| e.tick <= tick);
+        match index {
+            0 => DEFAULT_US_PER_QN,
+            _ => self.entries[index - 1].us_per_qn,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(tick: u64, us_per_qn: u32) -> TempoEntry {
+        TempoEntry { tick, us_per_qn }
+    }
+
+    #[test]
+    fn insert_keeps_ticks_sorted_and_stable() {
+        let mut map = TempoMap::new();
+        map.insert(entry(960, 400_000));
+        map.insert(entry(0, 600_000));
+        map.insert(entry(960, 300_000));
+        assert!(map.is_sorted());
+        assert_eq!(
+            map.entries,
+            [entry(0, 600_000), entry(960, 400_000), entry(960, 300_000)]
+        );
+    }
+
+    #[test]
+    fn tempo_at_uses_last_entry_at_or_before_tick() {
+        let map = TempoMap::from_entries(alloc::vec![entry(480, 400_000), entry(960, 300_000)]);
+        assert_eq!(map.tempo_at(0), DEFAULT_US_PER_QN);
+        assert_eq!(map.tempo_at(480), 400_000);
+        assert_eq!(map.tempo_at(959), 400_000);
+        assert_eq!(map.tempo_at(u64::MAX), 300_000);
+    }
+}
@@ -0,0 +1,137 @@
+//! SMPTE timecode divisions of Standard MIDI Files.
+//!
+//! An SMF with a timecode division counts ticks in fractions of a video
+//! frame instead of a quarter note. Positions convert to absolute time
+//! directly, with no tempo involved.
+
+use crate::sequence::AbsUnit;
+
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+
+/// Frame rate of a timecode division.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SmpteFps {
+    /// 24 frames per second.
+    Fps24,
+    /// 25 frames per second.
+    Fps25,
+    /// 29.97 drop-frame (SMF code `-29`).
+    ///
+    /// Frames run at exactly 30000/1001 per second. Drop-frame numbering only
+    /// skips timecode labels, so elapsed time follows the true rate.
+    Fps29_97Drop,
+    /// 30 frames per second.
+    Fps30,
+}
+
+impl SmpteFps {
+    /// Frames per second as an exact `(numerator, denominator)` fraction.
+    pub fn frame_rate(self) -> (u32, u32) {
+        match self {
+            SmpteFps::Fps24 => (24, 1),
+            SmpteFps::Fps25 => (25, 1),
+            SmpteFps::Fps29_97Drop => (30_000, 1_001),
+            SmpteFps::Fps30 => (30, 1),
+        }
+    }
+
+    /// Whether timecode labels use drop-frame numbering.
+    pub fn is_drop_frame(self) -> bool {
+        self == SmpteFps::Fps29_97Drop
+    }
+}
+
+/// Timecode division of an SMF: frame rate and ticks per frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SmpteTiming {
+    pub fps: SmpteFps,
+    /// Subframe resolution; must be non-zero for conversions to succeed.
+    pub ticks_per_frame: u8,
+}
+
+impl SmpteTiming {
+    pub fn new(fps: SmpteFps, ticks_per_frame: u8) -> Self {
+        SmpteTiming {
+            fps,
+            ticks_per_frame,
+        }
+    }
+
+    /// Ticks per second as an exact `(numerator, denominator)` fraction.
+    pub fn ticks_per_second(self) -> (u128, u128) {
+        let (num, den) = self.fps.frame_rate();
+        (num as u128 * self.ticks_per_frame as u128, den as u128)
+    }
+
+    /// Absolute position of `tick` in `unit`, rounded to the nearest unit.
+    ///
+    /// Returns `None` for a zero `ticks_per_frame` or when the result does
+    /// not fit in a `u64`.
+    pub fn tick_to_abs(self, tick: u64, unit: AbsUnit) -> Option<u64> {
+        let (num, den) = self.ticks_per_second();
+        let scaled = tick as u128 * NANOS_PER_SECOND * den;
+        let value = div_round(scaled, num * unit.nanos_per_unit() as u128)?;
+        u64::try_from(value).ok()
+    }
+
+    /// Tick nearest to the absolute position `time_abs` (in `unit`).
+    pub fn abs_to_tick(self, time_abs: u64, unit: AbsUnit) -> Option<u64> {
+        let (num, den) = self.ticks_per_second();
+        let scaled = unit.to_nanos(time_abs).checked_mul(num)?;
+        let value = div_round(scaled, NANOS_PER_SECOND * den)?;
+        u64::try_from(value).ok()
+    }
+}
+
+/// `value / divisor` rounded half up, or `None` for a zero divisor.
+fn div_round(value: u128, divisor: u128) -> Option<u128> {
+    let quotient = value.checked_div(divisor)?;
+    if value % divisor >= divisor - divisor / 2 {
+        Some(quotient + 1)
+    } else {
+        Some(quotient)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drop_frame_runs_at_the_true_rate() {
+        // An hour of drop-frame timecode labels 107_892 frames (108 labels
+        // are skipped every ten minutes), lasting 107_892 * 1_001 / 30_000 s.
+        let timing = SmpteTiming::new(SmpteFps::Fps29_97Drop, 80);
+        let ticks = 107_892 * 80;
+        assert_eq!(
+            timing.tick_to_abs(ticks, AbsUnit::Microseconds),
+            Some(3_599_996_400)
+        );
+        assert_eq!(
+            timing.abs_to_tick(3_599_996_400, AbsUnit::Microseconds),
+            Some(ticks)
+        );
+        // A single tick lasts 417.083… µs.
+        assert_eq!(timing.tick_to_abs(1, AbsUnit::Nanoseconds), Some(417_083));
+        assert_eq!(timing.tick_to_abs(1, AbsUnit::Microseconds), Some(417));
+    }
+
+    #[test]
+    fn integer_rates_round_trip_every_tick() {
+        for fps in [SmpteFps::Fps24, SmpteFps::Fps25, SmpteFps::Fps30] {
+            let timing = SmpteTiming::new(fps, 100);
+            for tick in (0..10_000).chain([u32::MAX as u64]) {
+                let abs = timing.tick_to_abs(tick, AbsUnit::Microseconds).unwrap();
+                assert_eq!(timing.abs_to_tick(abs, AbsUnit::Microseconds), Some(tick));
+            }
+        }
+        assert_eq!(
+            SmpteTiming::new(SmpteFps::Fps25, 40).tick_to_abs(1_000, AbsUnit::Microseconds),
+            Some(1_000_000)
+        );
+        assert_eq!(
+            SmpteTiming::new(SmpteFps::Fps25, 0).tick_to_abs(1, AbsUnit::Microseconds),
+            None
+        );
+    }
+}
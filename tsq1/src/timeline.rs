@@ -0,0 +1,452 @@
+//! Tick ↔ absolute time resolution for a whole sequence.
+//!
+//! A [`Timeline`] combines every timing source a file can carry, in this
+//! order of precedence:
+//!
+//! 1. `"SYNC"` anchors, when at least two sit at distinct ticks. Time is then
+//!    linearly interpolated between anchors (spec §5) and tempo is ignored.
+//! 2. `"TMAP"` entries, when any `"TMAP"` chunk has entries.
+//! 3. Musical-domain Meta `0x51` (Set Tempo) events from all tracks, when the
+//!    file has no tempo map.
+//! 4. The SMF default of 120 BPM ([`DEFAULT_US_PER_QN`]).
+//!
+//! When the tempo drives the timeline, a single `"SYNC"` anchor shifts it so
+//! the anchor's tick lands on its `time_abs`.
+//!
+//! Positions are computed from exact integer numerators and rounded down only
+//! once, at the end, so results do not drift however long the sequence is.
+
+use alloc::vec::Vec;
+
+use crate::event::{Domain, Event, EventKind, EventTime};
+use crate::sequence::{AbsUnit, Chunk, Sequence};
+use crate::sync::{SyncAnchor, SyncMap};
+use crate::tempo::{TempoEntry, TempoMap, DEFAULT_US_PER_QN};
+use crate::Error;
+
+/// Which timing source a [`Timeline`] resolved to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TimeSource {
+    /// Interpolation between `"SYNC"` anchors.
+    Sync,
+    /// `"TMAP"` tempo map.
+    TempoMap,
+    /// In-track Set Tempo meta events.
+    TempoEvents,
+    /// No timing information; 120 BPM throughout.
+    Default,
+}
+
+/// Absolute position of one event of a sequence.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TimedEvent<'a> {
+    /// Index of the event's track.
+    pub track: usize,
+    /// Index of the event within its track.
+    pub index: usize,
+    /// Position in the sequence's `AbsUnit`.
+    pub time_abs: u64,
+    pub event: &'a Event,
+}
+
+/// Start of a constant-tempo stretch.
+#[derive(Debug, Copy, Clone)]
+struct Segment {
+    tick: u64,
+    us_per_qn: u32,
+    /// Position of `tick` in nanoseconds multiplied by the PPQ.
+    start: u128,
+}
+
+#[derive(Debug, Clone)]
+enum Mapping {
+    Sync(SyncMap),
+    Tempo {
+        segments: Vec<Segment>,
+        /// Shift applied to tempo-derived positions, in nanoseconds
+        /// multiplied by the PPQ.
+        offset: i128,
+    },
+}
+
+/// Conversion between ticks and absolute time for a sequence.
+#[derive(Debug, Clone)]
+pub struct Timeline {
+    ppq: u16,
+    unit: AbsUnit,
+    source: TimeSource,
+    mapping: Mapping,
+}
+
+impl Timeline {
+    /// Resolve the timeline of `seq` from its header, `"TMAP"` and `"SYNC"`
+    /// chunks and tempo events, following the precedence in the module docs.
+    ///
+    /// Tempo events of all tracks are combined. For a file with
+    /// [sequential tracks](crate::Header::sequential_tracks), resolve each of
+    /// [`Sequence::patterns`] instead. Fails if the combined `"SYNC"`
+    /// anchors are not monotonic; see [`Timeline::with_sync`].
+    pub fn from_sequence(seq: &Sequence) -> Result<Self, Error> {
+        let mut tempo = TempoMap::new();
+        let mut sync = SyncMap::new();
+        for chunk in &seq.chunks {
+            match chunk {
+                Chunk::TempoMap(map) => map.entries.iter().for_each(|&e| tempo.insert(e)),
+                Chunk::Sync(map) => map.anchors.iter().for_each(|&a| sync.insert(a)),
+                _ => {}
+            }
+        }
+
+        let mut source = TimeSource::TempoMap;
+        if tempo.is_empty() {
+            for track in &seq.tracks {
+                for (time, event) in track.timed_events() {
+                    if let (EventTime::Musical(tick), Some(us_per_qn)) = (time, tempo_of(event)) {
+                        tempo.insert(TempoEntry { tick, us_per_qn });
+                    }
+                }
+            }
+            source = if tempo.is_empty() {
+                TimeSource::Default
+            } else {
+                TimeSource::TempoEvents
+            };
+        }
+
+        let mut timeline = Timeline::with_tempo_map(seq.header.ppq, seq.header.abs_unit, &tempo)?;
+        timeline.source = source;
+        timeline.with_sync(&sync)
+    }
+
+    /// Timeline driven by `tempo` alone. Entries need not be sorted; at equal
+    /// ticks the last entry wins.
+    pub fn with_tempo_map(ppq: u16, unit: AbsUnit, tempo: &TempoMap) -> Result<Self, Error> {
+        if ppq == 0 {
+            return Err(Error::Invalid("PPQ must be non-zero"));
+        }
+        let mut sorted = TempoMap::new();
+        tempo.entries.iter().for_each(|&e| sorted.insert(e));
+
+        let mut segments = alloc::vec![Segment {
+            tick: 0,
+            us_per_qn: DEFAULT_US_PER_QN,
+            start: 0,
+        }];
+        for entry in sorted.entries {
+            let last = segments[segments.len() - 1];
+            if entry.tick == last.tick {
+                segments.last_mut().unwrap().us_per_qn = entry.us_per_qn;
+            } else {
+                segments.push(Segment {
+                    tick: entry.tick,
+                    us_per_qn: entry.us_per_qn,
+                    start: last.start + span(entry.tick - last.tick, last.us_per_qn),
+                });
+            }
+        }
+
+        Ok(Timeline {
+            ppq,
+            unit,
+            source: if tempo.is_empty() {
+                TimeSource::Default
+            } else {
+                TimeSource::TempoMap
+            },
+            mapping: Mapping::Tempo {
+                segments,
+                offset: 0,
+            },
+        })
+    }
+
+    /// Apply `"SYNC"` anchors on top of the current mapping.
+    ///
+    /// Two or more anchors at distinct ticks replace the tempo entirely; a
+    /// single anchor (or several at one tick, where the last wins) shifts the
+    /// tempo-derived timeline onto it. Without anchors this is a no-op.
+    /// Anchors need not be sorted, but ordered by tick their times must not
+    /// decrease, or the mapping would not be invertible.
+    pub fn with_sync(mut self, sync: &SyncMap) -> Result<Self, Error> {
+        let mut sorted = SyncMap::new();
+        sync.anchors.iter().for_each(|&a| sorted.insert(a));
+        if !sorted.is_monotonic() {
+            return Err(Error::Invalid("SYNC anchor times decrease"));
+        }
+        let distinct = sorted.anchors.windows(2).any(|w| w[0].tick != w[1].tick);
+        if distinct {
+            self.source = TimeSource::Sync;
+            self.mapping = Mapping::Sync(sorted);
+        } else if let (Some(anchor), Mapping::Tempo { segments, offset }) =
+            (sorted.anchors.last(), &mut self.mapping)
+        {
+            let SyncAnchor { tick, time_abs } = *anchor;
+            let target = self.unit.to_nanos(time_abs) * self.ppq as u128;
+            *offset = target as i128 - tempo_position(segments, tick) as i128;
+        }
+        Ok(self)
+    }
+
+    pub fn ppq(&self) -> u16 {
+        self.ppq
+    }
+
+    pub fn abs_unit(&self) -> AbsUnit {
+        self.unit
+    }
+
+    /// The timing source the timeline resolved to.
+    pub fn source(&self) -> TimeSource {
+        self.source
+    }
+
+    /// Absolute position of `tick` in the timeline's `AbsUnit`, rounded down.
+    ///
+    /// Returns `None` when the position falls before zero, past `u64::MAX`,
+    /// or cannot be derived from the `"SYNC"` anchors.
+    pub fn tick_to_abs(&self, tick: u64) -> Option<u64> {
+        let nanos = self.tick_to_nanos(tick)?;
+        u64::try_from(nanos / self.unit.nanos_per_unit() as u128).ok()
+    }
+
+    /// Absolute position of `tick` in nanoseconds, rounded down.
+    pub fn tick_to_nanos(&self, tick: u64) -> Option<u128> {
+        match &self.mapping {
+            Mapping::Sync(sync) => sync.tick_to_nanos(tick, self.unit),
+            Mapping::Tempo { segments, offset } => {
+                let scaled = tempo_position(segments, tick) as i128 + offset;
+                u128::try_from(scaled).ok().map(|s| s / self.ppq as u128)
+            }
+        }
+    }
+
+    /// Tick position of `time_abs` (in the timeline's `AbsUnit`).
+    ///
+    /// When tempo drives the timeline this is the last tick that
+    /// [`Timeline::tick_to_abs`] does not place after `time_abs`, so the two
+    /// round-trip whenever a tick is longer than one unit. `"SYNC"`
+    /// interpolation rounds down as [`SyncMap::abs_to_tick`] does.
+    pub fn abs_to_tick(&self, time_abs: u64) -> Option<u64> {
+        match &self.mapping {
+            Mapping::Sync(sync) => sync.abs_to_tick(time_abs),
+            Mapping::Tempo { segments, offset } => {
+                let next = self.unit.to_nanos(time_abs) + self.unit.nanos_per_unit() as u128;
+                let end = next * self.ppq as u128;
+                last_tick_before(segments, end as i128 - offset)
+            }
+        }
+    }
+
+    /// Tick position of an absolute time in nanoseconds; see
+    /// [`Timeline::abs_to_tick`].
+    pub fn nanos_to_tick(&self, nanos: u128) -> Option<u64> {
+        match &self.mapping {
+            Mapping::Sync(sync) => sync.nanos_to_tick(nanos, self.unit),
+            Mapping::Tempo { segments, offset } => {
+                let end = (nanos + 1).checked_mul(self.ppq as u128)?;
+                last_tick_before(segments, i128::try_from(end).ok()? - offset)
+            }
+        }
+    }
+
+    /// Absolute position of `time`, converting musical positions.
+    pub fn to_abs(&self, time: EventTime) -> Option<u64> {
+        match time {
+            EventTime::Musical(tick) => self.tick_to_abs(tick),
+            EventTime::Absolute(time_abs) => Some(time_abs),
+        }
+    }
+
+    /// Every event of `seq` with its absolute position, merged across tracks.
+    ///
+    /// Events are ordered by position; ties keep track order and then the
+    /// order within each track. Fails if a musical event cannot be placed.
+    pub fn merged_events<'a>(&self, seq: &'a Sequence) -> Result<Vec<TimedEvent<'a>>, Error> {
+        let mut merged = Vec::new();
+        for (track, events) in seq.tracks.iter().enumerate() {
+            for (index, (time, event)) in events.timed_events().enumerate() {
+                let time_abs = self.to_abs(time).ok_or(Error::Invalid(
+                    "event position cannot be placed on the timeline",
+                ))?;
+                merged.push(TimedEvent {
+                    track,
+                    index,
+                    time_abs,
+                    event,
+                });
+            }
+        }
+        merged.sort_by_key(|timed| timed.time_abs);
+        Ok(merged)
+    }
+}
+
+/// Duration of `ticks` at `us_per_qn`, in nanoseconds multiplied by the PPQ.
+fn span(ticks: u64, us_per_qn: u32) -> u128 {
+    ticks as u128 * us_per_qn as u128 * 1_000
+}
+
+/// Tempo-derived position of `tick`, in nanoseconds multiplied by the PPQ.
+fn tempo_position(segments: &[Segment], tick: u64) -> u128 {
+    let index = segments.partition_point(|s| s.tick <= tick) - 1;
+    let segment = segments[index];
+    segment.start + span(tick - segment.tick, segment.us_per_qn)
+}
+
+/// The last tick whose tempo-derived position lies before `end` (in
+/// nanoseconds multiplied by the PPQ).
+fn last_tick_before(segments: &[Segment], end: i128) -> Option<u64> {
+    let last = u128::try_from(end).ok()?.checked_sub(1)?;
+    let index = segments.partition_point(|s| s.start <= last) - 1;
+    let segment = segments[index];
+    // A zero tempo can only be chosen as the final segment, where it holds
+    // every later tick.
+    let rate = span(1, segment.us_per_qn);
+    let ticks = last.checked_sub(segment.start)?.checked_div(rate)?;
+    segment.tick.checked_add(u64::try_from(ticks).ok()?)
+}
+
+/// Tempo of a musical-domain Set Tempo meta event.
+fn tempo_of(event: &Event) -> Option<u32> {
+    match (&event.domain, &event.kind) {
+        (Domain::Musical, EventKind::Meta(meta)) if meta.meta_type == 0x51 => match meta.data[..] {
+            [a, b, c] => Some(u32::from_be_bytes([0, a, b, c])),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{MetaEvent, MidiEvent};
+    use crate::sequence::Track;
+    use alloc::vec;
+
+    fn tempo_event(delta: u64, us_per_qn: u32) -> Event {
+        let bytes = us_per_qn.to_be_bytes();
+        Event::musical(
+            delta,
+            EventKind::Meta(MetaEvent::new(0x51, bytes[1..].to_vec())),
+        )
+    }
+
+    fn note(delta: u64) -> Event {
+        Event::musical(delta, EventKind::Midi(MidiEvent::new(0x90, 60, 100)))
+    }
+
+    fn entry(tick: u64, us_per_qn: u32) -> TempoEntry {
+        TempoEntry { tick, us_per_qn }
+    }
+
+    #[test]
+    fn hour_long_sequences_do_not_drift() {
+        // 333_333 µs per quarter note at 960 PPQ is 347.222… µs per tick.
+        let tempo = TempoMap::from_entries(vec![entry(0, 333_333)]);
+        let timeline = Timeline::with_tempo_map(960, AbsUnit::Nanoseconds, &tempo).unwrap();
+        let ticks_per_hour = 3_600_000_000_000u128 * 960 / 333_333_000;
+        let tick = ticks_per_hour as u64;
+        let expected = (tick as u128 * 333_333_000 / 960) as u64;
+        assert_eq!(timeline.tick_to_abs(tick), Some(expected));
+        assert_eq!(timeline.abs_to_tick(expected), Some(tick));
+        assert_eq!(timeline.abs_to_tick(expected - 348), Some(tick - 1));
+    }
+
+    #[test]
+    fn tempo_changes_accumulate_exactly() {
+        let tempo = TempoMap::from_entries(vec![entry(480, 250_000), entry(0, 1_000_000)]);
+        let timeline = Timeline::with_tempo_map(480, AbsUnit::Microseconds, &tempo).unwrap();
+        assert_eq!(timeline.source(), TimeSource::TempoMap);
+        assert_eq!(timeline.tick_to_abs(480), Some(1_000_000));
+        assert_eq!(timeline.tick_to_abs(960), Some(1_250_000));
+        assert_eq!(timeline.tick_to_abs(1), Some(2_083));
+        assert_eq!(timeline.abs_to_tick(1_125_000), Some(720));
+        assert_eq!(timeline.abs_to_tick(999_999), Some(479));
+    }
+
+    #[test]
+    fn precedence_prefers_sync_then_tempo_map_then_events() {
+        let mut seq = Sequence::new(480);
+        seq.tracks.push(Track::with_events(vec![
+            tempo_event(0, 1_000_000),
+            note(480),
+        ]));
+        let timeline = Timeline::from_sequence(&seq).unwrap();
+        assert_eq!(timeline.source(), TimeSource::TempoEvents);
+        assert_eq!(timeline.tick_to_abs(480), Some(1_000_000));
+
+        seq.chunks
+            .push(Chunk::TempoMap(TempoMap::from_entries(vec![entry(
+                0, 250_000,
+            )])));
+        let timeline = Timeline::from_sequence(&seq).unwrap();
+        assert_eq!(timeline.source(), TimeSource::TempoMap);
+        assert_eq!(timeline.tick_to_abs(480), Some(250_000));
+
+        // A single anchor shifts the tempo map onto it.
+        seq.chunks
+            .push(Chunk::Sync(SyncMap::from_anchors(vec![SyncAnchor {
+                tick: 480,
+                time_abs: 10_000_000,
+            }])));
+        let timeline = Timeline::from_sequence(&seq).unwrap();
+        assert_eq!(timeline.source(), TimeSource::TempoMap);
+        assert_eq!(timeline.tick_to_abs(0), Some(9_750_000));
+        assert_eq!(timeline.abs_to_tick(9_000_000), None);
+
+        // Two anchors take over completely.
+        seq.chunks
+            .push(Chunk::Sync(SyncMap::from_anchors(vec![SyncAnchor {
+                tick: 960,
+                time_abs: 12_000_000,
+            }])));
+        let timeline = Timeline::from_sequence(&seq).unwrap();
+        assert_eq!(timeline.source(), TimeSource::Sync);
+        assert_eq!(timeline.tick_to_abs(720), Some(11_000_000));
+        assert_eq!(timeline.abs_to_tick(11_000_000), Some(720));
+
+        // Anchors whose times go back cannot be inverted.
+        let anchors = [(0, 100), (10, 50), (20, 100), (30, 50)];
+        seq.chunks = vec![Chunk::Sync(SyncMap::from_anchors(
+            anchors
+                .iter()
+                .map(|&(tick, time_abs)| SyncAnchor { tick, time_abs })
+                .collect(),
+        ))];
+        assert!(matches!(
+            Timeline::from_sequence(&seq),
+            Err(Error::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn merges_events_across_tracks_and_domains() {
+        let mut seq = Sequence::new(480);
+        seq.tracks
+            .push(Track::with_events(vec![note(480), note(480)]));
+        seq.tracks.push(Track::with_events(vec![
+            Event::absolute(500_000, EventKind::Midi(MidiEvent::new(0x91, 62, 90))),
+            note(0),
+            Event::absolute(250_000, EventKind::Midi(MidiEvent::new(0x81, 62, 0))),
+        ]));
+        let timeline = Timeline::from_sequence(&seq).unwrap();
+        assert_eq!(timeline.source(), TimeSource::Default);
+        let merged: Vec<(usize, usize, u64)> = timeline
+            .merged_events(&seq)
+            .unwrap()
+            .iter()
+            .map(|timed| (timed.track, timed.index, timed.time_abs))
+            .collect();
+        assert_eq!(
+            merged,
+            vec![
+                (1, 1, 0),
+                (0, 0, 500_000),
+                (1, 0, 500_000),
+                (1, 2, 750_000),
+                (0, 1, 1_000_000),
+            ]
+        );
+    }
+}
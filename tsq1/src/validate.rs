@@ -0,0 +1,339 @@
+//! Conformance checking against the TSQ1 specification.
+//!
+//! [`validate`] goes further than [`read`], which accepts anything it can
+//! decode: it also reports what the spec forbids or discourages but a
+//! reader can get past. Findings are split into errors,
+//! which break a spec requirement, and warnings, which do not but suggest a
+//! faulty or outdated writer.
+
+use alloc::vec::Vec;
+
+use crate::event::OscFormat;
+use crate::iter::{self, ChunkRef, DecodeError, EventKindRef, EventRef};
+use crate::sequence::{Header, FLAG_MIDI_THREE_BYTES, FLAG_SEQUENTIAL_TRACKS};
+use crate::{meta_from_payload, osc, read, Error, FLAG_SYSEX_STATUS_IN_PAYLOAD};
+
+/// Every `Flags` bit this crate knows.
+const KNOWN_FLAGS: u16 =
+    FLAG_SYSEX_STATUS_IN_PAYLOAD | FLAG_MIDI_THREE_BYTES | FLAG_SEQUENTIAL_TRACKS;
+
+/// Findings of [`validate`], each ordered by offset.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Violations of the spec.
+    pub errors: Vec<DecodeError>,
+    /// Legal but suspicious encodings.
+    pub warnings: Vec<DecodeError>,
+}
+
+impl Report {
+    /// Whether the input conforms to the spec; warnings are allowed.
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Check a complete TSQ1 file against the spec.
+///
+/// Errors:
+/// - a malformed header, a nonzero `Reserved` byte or reserved `Flags`
+///   bits, and chunks or events that cannot be decoded (a track is checked
+///   up to the first of those);
+/// - MIDI status or data bytes out of range, OSC RAW payloads that are not
+///   4-byte aligned OSC packets, meta payloads of the wrong length for
+///   their SMF type, and SysEx payloads missing the status byte that
+///   [`FLAG_SYSEX_STATUS_IN_PAYLOAD`] promises;
+/// - `"TMAP"` entries out of tick order or with a zero tempo, `"SYNC"`
+///   anchors whose ticks do not increase or whose times decrease, and
+///   malformed or unsorted `"MARK"` locators.
+///
+/// Warnings: a `TrackCount` that does not match the tracks present, VLQs
+/// padded with leading zero groups, and a nonzero padding byte in the
+/// three-byte MIDI layout.
+pub fn validate(data: &[u8]) -> Report {
+    let mut report = Report::default();
+    let (header, chunks) = match iter::chunks(data) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            report.errors.push(err);
+            return report;
+        }
+    };
+    if header.reserved != 0 {
+        report
+            .errors
+            .push(invalid(9, "Reserved header byte is not 0"));
+    }
+    if header.flags & !KNOWN_FLAGS != 0 {
+        report
+            .errors
+            .push(invalid(12, "reserved Flags bits are set"));
+    }
+
+    let mut tracks = 0;
+    for chunk in chunks.salvage(&mut report.errors) {
+        match &chunk.id {
+            b"TRK " => {
+                tracks += 1;
+                check_track(data, &chunk, &header, &mut report);
+            }
+            b"TMAP" => check_tempo_map(&chunk, &mut report),
+            b"SYNC" => check_sync(&chunk, &mut report),
+            b"MARK" => {
+                if let Err((offset, err)) = read::read_markers_at(chunk.data) {
+                    report
+                        .errors
+                        .push(chunk.error_at(chunk.offset + 8 + offset, err));
+                }
+            }
+            _ => {}
+        }
+    }
+    if tracks != header.track_count as usize {
+        let message = "TrackCount does not match the number of tracks";
+        report.warnings.push(invalid(10, message));
+    }
+
+    report.errors.sort_by_key(|err| err.offset);
+    report.warnings.sort_by_key(|err| err.offset);
+    report
+}
+
+fn invalid(offset: usize, message: &'static str) -> DecodeError {
+    DecodeError::new(offset, Error::Invalid(message))
+}
+
+fn check_track(data: &[u8], chunk: &ChunkRef<'_>, header: &Header, report: &mut Report) {
+    for (index, event) in chunk.events(header.flags).enumerate() {
+        match event {
+            Ok(event) => check_event(data, chunk, index, &event, header, report),
+            Err(err) => report.errors.push(err),
+        }
+    }
+}
+
+fn check_event(
+    data: &[u8],
+    chunk: &ChunkRef<'_>,
+    index: usize,
+    event: &EventRef<'_>,
+    header: &Header,
+    report: &mut Report,
+) {
+    let at = |offset, error| chunk.error_at(offset, error).at_event(index);
+
+    // The delta follows the header byte; payload lengths follow the delta
+    // and, except for SysEx, a one-byte format or type.
+    let delta = event.offset + 1;
+    let after_delta = delta + vlq_len(&data[delta..]);
+    let length = match event.kind {
+        EventKindRef::Midi(_) => None,
+        EventKindRef::SysEx(_) => Some(after_delta),
+        _ => Some(after_delta + 1),
+    };
+    for offset in [Some(delta), length].into_iter().flatten() {
+        // A minimal encoding never starts with a zero group.
+        if data[offset] == 0x80 {
+            let message = "VLQ is not minimally encoded";
+            report.warnings.push(at(offset, Error::Invalid(message)));
+        }
+    }
+
+    let result = match event.kind {
+        EventKindRef::Osc {
+            format: OscFormat::Raw,
+            data,
+        } => osc::validate_raw(data),
+        EventKindRef::Midi(midi) => {
+            if midi.data1 > 0x7F || midi.data2 > 0x7F {
+                Err(Error::Invalid("MIDI data byte exceeds 7 bits"))
+            } else {
+                if midi.is_single_data_byte() && header.midi_three_bytes() && midi.data2 != 0 {
+                    let message = "MIDI padding byte is not 0";
+                    report
+                        .warnings
+                        .push(at(event.offset, Error::Invalid(message)));
+                }
+                Ok(())
+            }
+        }
+        EventKindRef::Meta { meta_type, data } => meta_from_payload(meta_type, data).map(|_| ()),
+        EventKindRef::SysEx(data) => match header.flags & FLAG_SYSEX_STATUS_IN_PAYLOAD != 0 {
+            true if !matches!(data.first(), Some(0xF0 | 0xF7)) => {
+                Err(Error::Invalid("SysEx payload lacks its status byte"))
+            }
+            _ => Ok(()),
+        },
+        EventKindRef::Osc { .. } | EventKindRef::Custom { .. } => Ok(()),
+    };
+    if let Err(err) = result {
+        report.errors.push(at(event.offset, err));
+    }
+}
+
+/// Number of bytes in the VLQ at the start of `data`.
+fn vlq_len(data: &[u8]) -> usize {
+    data.iter()
+        .position(|byte| byte & 0x80 == 0)
+        .map_or(data.len(), |last| last + 1)
+}
+
+fn check_tempo_map(chunk: &ChunkRef<'_>, report: &mut Report) {
+    let start = chunk.offset + 8;
+    let at = |offset, message| chunk.error_at(offset, Error::Invalid(message));
+    if !chunk.data.len().is_multiple_of(12) {
+        let message = "TMAP chunk length is not a multiple of 12";
+        report.errors.push(at(start, message));
+        return;
+    }
+    let mut previous = None;
+    for (index, entry) in chunk.data.chunks_exact(12).enumerate() {
+        let offset = start + index * 12;
+        let tick = u64_le(&entry[..8]);
+        if previous.is_some_and(|previous| tick < previous) {
+            let message = "TMAP entries are not sorted by tick";
+            report.errors.push(at(offset, message));
+        }
+        if entry[8..] == [0; 4] {
+            report
+                .errors
+                .push(at(offset, "TMAP entry has a zero tempo"));
+        }
+        previous = Some(tick);
+    }
+}
+
+fn check_sync(chunk: &ChunkRef<'_>, report: &mut Report) {
+    let start = chunk.offset + 8;
+    let at = |offset, message| chunk.error_at(offset, Error::Invalid(message));
+    if !chunk.data.len().is_multiple_of(16) {
+        let message = "SYNC chunk length is not a multiple of 16";
+        report.errors.push(at(start, message));
+        return;
+    }
+    let mut previous: Option<(u64, u64)> = None;
+    for (index, anchor) in chunk.data.chunks_exact(16).enumerate() {
+        let (tick, time_abs) = (u64_le(&anchor[..8]), u64_le(&anchor[8..]));
+        if previous.is_some_and(|previous| tick <= previous.0 || time_abs < previous.1) {
+            let message = "SYNC anchors are not monotonic";
+            report.errors.push(at(start + index * 16, message));
+        }
+        previous = Some((tick, time_abs));
+    }
+}
+
+fn u64_le(bytes: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(bytes);
+    u64::from_le_bytes(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{Event, EventKind, MetaEvent, MidiEvent};
+    use crate::sequence::{Chunk, Sequence, Track};
+    use crate::tempo::{TempoEntry, TempoMap};
+    use alloc::vec;
+
+    fn findings(found: &[DecodeError]) -> Vec<(usize, &'static str)> {
+        found
+            .iter()
+            .map(|err| match err.error.cause() {
+                Error::Invalid(message) => (err.offset, *message),
+                other => panic!("unexpected {other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn conforming_files_pass() {
+        let mut seq = Sequence::new(480);
+        seq.header.flags = FLAG_MIDI_THREE_BYTES;
+        seq.tracks.push(Track::with_events(vec![
+            Event::musical(0, EventKind::Midi(MidiEvent::new(0xC0, 5, 0))),
+            Event::musical(480, EventKind::Meta(MetaEvent::new(0x51, vec![7, 161, 32]))),
+        ]));
+        seq.chunks
+            .push(Chunk::TempoMap(TempoMap::from_entries(vec![TempoEntry {
+                tick: 0,
+                us_per_qn: 500_000,
+            }])));
+        let report = validate(&seq.to_vec().unwrap());
+        assert!(report.is_valid());
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn reports_errors_and_warnings_with_locations() {
+        let mut tsq = Vec::new();
+        crate::write_header(&mut tsq, 480, crate::AbsUnit::Microseconds, 2, 0x0012);
+        tsq[9] = 1;
+        let track = [
+            // Program change with nonzero padding and a padded delta.
+            &[0x01, 0x80, 0x00, 0xC0, 0x05, 0x01][..],
+            // Tempo meta of the wrong length.
+            &[0x02, 0x00, 0x51, 0x02, 0x07, 0xA1],
+            // Note on with an 8-bit velocity.
+            &[0x01, 0x00, 0x90, 0x3C, 0xFF],
+        ]
+        .concat();
+        for (id, data) in [
+            (b"TRK ", &track[..]),
+            (
+                b"TMAP",
+                &[[9, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0], [0; 12]].concat()[..],
+            ),
+            (b"SYNC", &[[5; 16], [4; 16]].concat()[..]),
+        ] {
+            tsq.extend_from_slice(id);
+            tsq.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            tsq.extend_from_slice(data);
+        }
+        tsq.extend_from_slice(b"MAR");
+
+        let report = validate(&tsq);
+        assert!(!report.is_valid());
+        let tmap = 14 + 8 + track.len() + 8;
+        let sync = tmap + 24 + 8;
+        assert_eq!(
+            findings(&report.errors),
+            [
+                (9, "Reserved header byte is not 0"),
+                (12, "reserved Flags bits are set"),
+                (28, "tempo meta must be 3 bytes"),
+                (34, "MIDI data byte exceeds 7 bits"),
+                (tmap + 12, "TMAP entries are not sorted by tick"),
+                (tmap + 12, "TMAP entry has a zero tempo"),
+                (sync + 16, "SYNC anchors are not monotonic"),
+                (sync + 32, "TSQ chunk header truncated"),
+            ]
+        );
+        assert_eq!(
+            (report.errors[3].track, report.errors[3].event),
+            (Some(0), Some(2))
+        );
+        assert_eq!(
+            findings(&report.warnings),
+            [
+                (10, "TrackCount does not match the number of tracks"),
+                (22, "MIDI padding byte is not 0"),
+                (23, "VLQ is not minimally encoded"),
+            ]
+        );
+
+        let report = validate(b"TSQ1");
+        assert_eq!(findings(&report.errors), [(4, "TSQ header truncated")]);
+
+        // MARK findings point at the entry at fault.
+        let entry = |pos: u64| [&[0][..], &pos.to_le_bytes(), &[0, 0]].concat();
+        let mark = [entry(5), entry(9), entry(7)].concat();
+        let mut tsq = Vec::new();
+        crate::write_header(&mut tsq, 480, crate::AbsUnit::Microseconds, 0, 0);
+        tsq.extend_from_slice(b"MARK");
+        tsq.extend_from_slice(&(mark.len() as u32).to_le_bytes());
+        tsq.extend_from_slice(&mark);
+        let message = "MARK locators are not sorted by position within each pos_kind";
+        assert_eq!(findings(&validate(&tsq).errors), [(22 + 22, message)]);
+    }
+}
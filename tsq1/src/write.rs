@@ -0,0 +1,478 @@
+//! Native TSQ1 encoder for owned [`Sequence`]s.
+
+use alloc::vec::Vec;
+
+use crate::custom::CustomRegistry;
+use crate::event::{Domain, Event, EventKind};
+use crate::marker::{locators_sorted, Locator};
+use crate::sequence::{Chunk, Header, Sequence, Track, MAGIC};
+use crate::sync::SyncAnchor;
+use crate::tempo::TempoEntry;
+use crate::{write_vlq, Error};
+
+impl Sequence {
+    /// Serialize the sequence into a new buffer.
+    ///
+    /// Chunks are emitted in spec order: every `"TRK "` chunk, then `"TMAP"`,
+    /// `"SYNC"` and `"MARK"`, then unknown chunks. Chunks of the same kind keep
+    /// their relative order. `TrackCount` is recomputed from `tracks`.
+    ///
+    /// A sequence read with [`crate::read`] from a file in another layout
+    /// keeps that file's chunk order and `header.track_count` as long as its
+    /// tracks and chunk IDs are unchanged, so the file is reproduced byte for
+    /// byte. [`Sequence::normalize`] drops that layout.
+    pub fn to_vec(&self) -> Result<Vec<u8>, Error> {
+        self.to_vec_with_registry(&CustomRegistry::new())
+    }
+
+    /// Serialize the sequence, encoding typed custom event values with the
+    /// codecs in `registry`. See [`Sequence::to_vec`].
+    pub fn to_vec_with_registry(&self, registry: &CustomRegistry) -> Result<Vec<u8>, Error> {
+        let recorded = self.layout.as_ref().and_then(|layout| layout.ids_for(self));
+        let track_count = match recorded {
+            Some(_) => self.header.track_count,
+            None => u16::try_from(self.tracks.len())
+                .map_err(|_| Error::DataOverflow("too many tracks"))?,
+        };
+
+        let mut out = Vec::new();
+        write_file_header(&mut out, &self.header, track_count);
+
+        let mut body = Vec::new();
+        let three_bytes = self.header.midi_three_bytes();
+        let mut tracks = self.tracks.iter();
+        match recorded {
+            Some(ids) => {
+                let mut chunks = self.chunks.iter();
+                for id in ids {
+                    body.clear();
+                    if id == b"TRK " {
+                        let track = tracks.next().expect("layout fits the tracks");
+                        encode_track(track, three_bytes, registry, &mut body)?;
+                    } else {
+                        let chunk = chunks.next().expect("layout fits the chunks");
+                        encode_chunk(chunk, &mut body)?;
+                    }
+                    push_chunk(&mut out, id, &body)?;
+                }
+            }
+            None => {
+                for track in tracks {
+                    body.clear();
+                    encode_track(track, three_bytes, registry, &mut body)?;
+                    push_chunk(&mut out, b"TRK ", &body)?;
+                }
+                let mut chunks: Vec<&Chunk> = self.chunks.iter().collect();
+                chunks.sort_by_key(|chunk| chunk.rank());
+                for chunk in chunks {
+                    body.clear();
+                    encode_chunk(chunk, &mut body)?;
+                    push_chunk(&mut out, &chunk.id(), &body)?;
+                }
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Forget the layout of the file the sequence was read from, so that it
+    /// is written in spec order with `TrackCount` recomputed, and set
+    /// `header.track_count` to match.
+    pub fn normalize(&mut self) {
+        self.layout = None;
+        self.header.track_count = self.tracks.len().try_into().unwrap_or(u16::MAX);
+    }
+
+    /// Serialize the sequence into `writer`. See [`Sequence::to_vec`].
+    #[cfg(feature = "std")]
+    pub fn write_to<W: std::io::Write>(&self, writer: &mut W) -> Result<(), Error> {
+        let bytes = self.to_vec()?;
+        writer.write_all(&bytes)?;
+        Ok(())
+    }
+}
+
+pub(crate) fn write_file_header(out: &mut Vec<u8>, header: &Header, track_count: u16) {
+    out.extend_from_slice(&MAGIC);
+    out.extend_from_slice(&header.version.to_le_bytes());
+    out.extend_from_slice(&header.ppq.to_le_bytes());
+    out.push(header.abs_unit.as_u8());
+    out.push(header.reserved);
+    out.extend_from_slice(&track_count.to_le_bytes());
+    out.extend_from_slice(&header.flags.to_le_bytes());
+}
+
+pub(crate) fn push_chunk(out: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) -> Result<(), Error> {
+    let len = u32::try_from(data.len()).map_err(|_| Error::DataOverflow("chunk too large"))?;
+    out.extend_from_slice(id);
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(data);
+    Ok(())
+}
+
+fn encode_track(
+    track: &Track,
+    three_bytes: bool,
+    registry: &CustomRegistry,
+    out: &mut Vec<u8>,
+) -> Result<(), Error> {
+    let mut payload = Vec::new();
+    for event in &track.events {
+        write_event(event, three_bytes, registry, &mut payload, out)?;
+    }
+    Ok(())
+}
+
+/// Reject events whose payload cannot be encoded as-is.
+fn check_event(event: &Event) -> Result<(), Error> {
+    match &event.kind {
+        EventKind::Osc(osc) => osc.validate(),
+        EventKind::Midi(midi) if !(0x80..=0xEF).contains(&midi.status) => {
+            Err(Error::Invalid("invalid MIDI status byte"))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Append the encoded form of `event` to `out`, using `scratch` to stage
+/// custom payloads. `three_bytes` selects the MIDI layout.
+pub(crate) fn write_event(
+    event: &Event,
+    three_bytes: bool,
+    registry: &CustomRegistry,
+    scratch: &mut Vec<u8>,
+    out: &mut Vec<u8>,
+) -> Result<(), Error> {
+    check_event(event)?;
+    out.push(event.header_byte());
+    write_vlq(event.delta, out);
+    match &event.kind {
+        EventKind::Osc(osc) => {
+            out.push(osc.format.as_u8());
+            write_len_prefixed(&osc.data, out);
+        }
+        EventKind::Midi(midi) => {
+            out.push(midi.status);
+            out.push(midi.data1);
+            if three_bytes || !midi.is_single_data_byte() {
+                out.push(midi.data2);
+            }
+        }
+        EventKind::Meta(meta) => {
+            out.push(meta.meta_type);
+            write_len_prefixed(&meta.data, out);
+        }
+        EventKind::SysEx(data) => write_len_prefixed(data, out),
+        EventKind::Custom(custom) => {
+            scratch.clear();
+            registry.encode(custom, scratch)?;
+            out.push(custom.type_id);
+            write_len_prefixed(scratch, out);
+        }
+    }
+    Ok(())
+}
+
+fn write_len_prefixed(data: &[u8], out: &mut Vec<u8>) {
+    write_vlq(data.len() as u64, out);
+    out.extend_from_slice(data);
+}
+
+pub(crate) fn encode_chunk(chunk: &Chunk, out: &mut Vec<u8>) -> Result<(), Error> {
+    match chunk {
+        Chunk::TempoMap(map) => {
+            for TempoEntry { tick, us_per_qn } in &map.entries {
+                out.extend_from_slice(&tick.to_le_bytes());
+                out.extend_from_slice(&us_per_qn.to_le_bytes());
+            }
+        }
+        Chunk::Sync(map) => {
+            for SyncAnchor { tick, time_abs } in &map.anchors {
+                out.extend_from_slice(&tick.to_le_bytes());
+                out.extend_from_slice(&time_abs.to_le_bytes());
+            }
+        }
+        Chunk::Markers(locators) => {
+            if !locators_sorted(locators) {
+                return Err(Error::Invalid(
+                    "MARK locators are not sorted by position within each pos_kind",
+                ));
+            }
+            let start = out.len();
+            for locator in locators {
+                encode_locator(locator, out);
+            }
+            // Colors carry no presence flag; refuse entries that could not
+            // be told apart when read back.
+            crate::read::read_markers(&out[start..])?;
+        }
+        Chunk::Unknown(raw) => out.extend_from_slice(&raw.data),
+    }
+    Ok(())
+}
+
+fn encode_locator(locator: &Locator, out: &mut Vec<u8>) {
+    out.push(match locator.pos_kind {
+        Domain::Musical => 0,
+        Domain::Absolute => 1,
+    });
+    out.extend_from_slice(&locator.pos.to_le_bytes());
+    write_len_prefixed(locator.name.as_bytes(), out);
+    out.push(locator.class);
+    if let Some(color) = locator.color {
+        out.extend_from_slice(&color.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{CustomEvent, EventTime, MetaEvent, MidiEvent, OscEvent, OscFormat};
+    use crate::sequence::{AbsUnit, RawChunk, FLAG_MIDI_THREE_BYTES};
+    use crate::sync::SyncMap;
+    use crate::tempo::TempoMap;
+    use alloc::vec;
+
+    fn locator(position: EventTime, name: &str, class: u8, color: Option<u32>) -> Locator {
+        Locator {
+            class,
+            color,
+            ..Locator::new(position, name)
+        }
+    }
+
+    fn corpus() -> Vec<Sequence> {
+        let empty = Sequence::new(480);
+
+        let mut notes = Sequence::new(960);
+        notes.header.flags = 0;
+        notes.tracks.push(Track::with_events(vec![
+            Event::musical(0, EventKind::Meta(MetaEvent::new(0x03, b"Lead".to_vec()))),
+            Event::musical(0, EventKind::Midi(MidiEvent::new(0x90, 60, 100))),
+            Event::musical(480, EventKind::Midi(MidiEvent::new(0x80, 60, 0))),
+            Event::musical(0, EventKind::Midi(MidiEvent::new(0xC1, 5, 0))),
+            Event::musical(0x0FFF_FFFF, EventKind::Midi(MidiEvent::new(0xE0, 0, 64))),
+            Event::musical(0, EventKind::Meta(MetaEvent::new(0x2F, vec![]))),
+        ]));
+        notes.tracks.push(Track::new());
+
+        let mut padded = notes.clone();
+        padded.header.flags = FLAG_MIDI_THREE_BYTES;
+        padded.tracks[0].push(Event::musical(
+            0,
+            EventKind::Midi(MidiEvent::new(0xD3, 90, 0)),
+        ));
+
+        let mut mixed = Sequence::new(96);
+        mixed.header.abs_unit = AbsUnit::Nanoseconds;
+        mixed.header.flags = crate::FLAG_SYSEX_STATUS_IN_PAYLOAD;
+        mixed.tracks.push(Track::with_events(vec![
+            Event::musical(
+                240,
+                EventKind::Osc(OscEvent {
+                    format: OscFormat::Raw,
+                    data: b"/light/flash\0\0\0\0,i\0\0\0\0\0\x01".to_vec(),
+                }),
+            ),
+            Event::absolute(150_000, EventKind::Midi(MidiEvent::new(0x90, 0x3C, 0x64))),
+            Event::absolute(u64::MAX, EventKind::SysEx(vec![0xF0, 0x7E, 0x7F])),
+            Event::musical(1, EventKind::Custom(CustomEvent::new(0x10, vec![0; 200]))),
+        ]));
+        mixed.chunks = vec![
+            Chunk::TempoMap(TempoMap::from_entries(vec![
+                TempoEntry {
+                    tick: 0,
+                    us_per_qn: 500_000,
+                },
+                TempoEntry {
+                    tick: 1920,
+                    us_per_qn: 400_000,
+                },
+            ])),
+            Chunk::Sync(SyncMap::from_anchors(vec![SyncAnchor {
+                tick: 0,
+                time_abs: 0,
+            }])),
+            Chunk::Markers(vec![
+                locator(EventTime::Musical(0), "Intro", 0x00, Some(0xFF00FF00)),
+                locator(EventTime::Musical(1024), "Drop \u{1F680}", 0x20, None),
+                locator(EventTime::Absolute(90_000_000), "", 0x7F, Some(0x01000000)),
+                locator(EventTime::Absolute(90_000_000), "Cue", 0x55, None),
+            ]),
+            Chunk::Unknown(RawChunk {
+                id: *b"XTRA",
+                data: vec![9, 8, 7],
+            }),
+            Chunk::Unknown(RawChunk {
+                id: *b"ZERO",
+                data: vec![],
+            }),
+        ];
+
+        vec![empty, notes, padded, mixed]
+    }
+
+    #[test]
+    fn corpus_roundtrips_byte_exact() {
+        for seq in corpus() {
+            let bytes = seq.to_vec().expect("encoding succeeds");
+            let decoded = crate::read(&bytes).expect("decoding succeeds");
+            let mut expected = seq.clone();
+            expected.header.track_count = seq.tracks.len() as u16;
+            assert_eq!(decoded, expected);
+            assert_eq!(decoded.to_vec().expect("re-encoding succeeds"), bytes);
+        }
+    }
+
+    #[test]
+    fn converter_output_roundtrips_byte_exact() {
+        let smf = midly::Smf {
+            header: midly::Header::new(
+                midly::Format::Parallel,
+                midly::Timing::Metrical(480.into()),
+            ),
+            tracks: vec![
+                vec![midly::TrackEvent {
+                    delta: 0.into(),
+                    kind: midly::TrackEventKind::Meta(midly::MetaMessage::Tempo(500_000.into())),
+                }],
+                vec![
+                    midly::TrackEvent {
+                        delta: 0.into(),
+                        kind: midly::TrackEventKind::SysEx(&[0x41, 0x10, 0xF7]),
+                    },
+                    midly::TrackEvent {
+                        delta: 10.into(),
+                        kind: midly::TrackEventKind::Midi {
+                            channel: 3.into(),
+                            message: midly::MidiMessage::ProgramChange { program: 7.into() },
+                        },
+                    },
+                ],
+            ],
+        };
+        let mut midi = Vec::new();
+        smf.write(&mut midi).expect("writing SMF succeeds");
+
+        let tsq = crate::convert_midi_to_tsq_vec(&midi).expect("conversion succeeds");
+        let seq = crate::read(&tsq).expect("decoding succeeds");
+        assert_eq!(seq.to_vec().expect("encoding succeeds"), tsq);
+    }
+
+    #[test]
+    fn rejects_invalid_osc_raw_payload() {
+        let mut seq = Sequence::new(480);
+        seq.tracks.push(Track::with_events(vec![Event::musical(
+            0,
+            EventKind::Osc(OscEvent {
+                format: OscFormat::Raw,
+                data: b"light".to_vec(),
+            }),
+        )]));
+        assert!(matches!(seq.to_vec(), Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn rejects_unsorted_locators() {
+        let mut seq = Sequence::new(480);
+        seq.chunks.push(Chunk::Markers(vec![
+            locator(EventTime::Musical(960), "B", 0x00, None),
+            locator(EventTime::Absolute(0), "Sync", 0x20, None),
+            locator(EventTime::Musical(0), "A", 0x00, None),
+        ]));
+        assert!(matches!(seq.to_vec(), Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn normalizes_chunk_order_and_track_count() {
+        let mut seq = Sequence::new(480);
+        seq.header.track_count = 7;
+        seq.chunks = vec![
+            Chunk::Unknown(RawChunk {
+                id: *b"XTRA",
+                data: vec![],
+            }),
+            Chunk::Sync(SyncMap::new()),
+            Chunk::TempoMap(TempoMap::new()),
+        ];
+        seq.tracks.push(Track::new());
+
+        let bytes = seq.to_vec().expect("encoding succeeds");
+        assert_eq!(u16::from_le_bytes([bytes[10], bytes[11]]), 1);
+        let ids: Vec<&[u8]> = bytes[14..].chunks(8).map(|c| &c[..4]).collect();
+        assert_eq!(ids, vec![&b"TRK "[..], b"TMAP", b"SYNC", b"XTRA"]);
+    }
+
+    /// A file with a `TrackCount` of 3 for two tracks, an unknown chunk
+    /// between the tracks and `"MARK"` before `"TMAP"`.
+    fn scrambled() -> Vec<u8> {
+        let note = Track::with_events(vec![Event::musical(
+            0,
+            EventKind::Midi(MidiEvent::new(0x90, 60, 100)),
+        )]);
+        let mut track = Vec::new();
+        encode_track(&note, false, &CustomRegistry::new(), &mut track).unwrap();
+
+        let mut out = Vec::new();
+        crate::write_header(&mut out, 480, AbsUnit::Microseconds, 3, 0);
+        push_chunk(&mut out, b"TRK ", &track).unwrap();
+        push_chunk(&mut out, b"XTRA", &[1, 2]).unwrap();
+        push_chunk(&mut out, b"TRK ", &[]).unwrap();
+        push_chunk(&mut out, b"MARK", &[]).unwrap();
+        push_chunk(&mut out, b"TMAP", &[]).unwrap();
+        out
+    }
+
+    fn chunk_ids(bytes: &[u8]) -> Vec<[u8; 4]> {
+        let mut ids = Vec::new();
+        let mut rest = &bytes[14..];
+        while !rest.is_empty() {
+            let len = u32::from_le_bytes(rest[4..8].try_into().unwrap()) as usize;
+            ids.push(rest[..4].try_into().unwrap());
+            rest = &rest[8 + len..];
+        }
+        ids
+    }
+
+    #[test]
+    fn unchanged_files_keep_their_layout() {
+        let bytes = scrambled();
+        let seq = crate::read(&bytes).expect("decoding succeeds");
+        assert_eq!(seq.header.track_count, 3);
+        assert_eq!(seq.to_vec().expect("encoding succeeds"), bytes);
+        let recovered = crate::read_lenient(&bytes).expect("decoding succeeds");
+        assert_eq!(recovered.value.to_vec().expect("encoding succeeds"), bytes);
+
+        let mut edited = seq.clone();
+        edited.header.flags = FLAG_MIDI_THREE_BYTES;
+        edited.tracks[1].push(Event::musical(
+            0,
+            EventKind::Meta(MetaEvent::new(0x2F, vec![])),
+        ));
+        let out = edited.to_vec().expect("encoding succeeds");
+        assert_eq!(chunk_ids(&out), chunk_ids(&bytes));
+
+        let canonical = [*b"TRK ", *b"TRK ", *b"TMAP", *b"MARK", *b"XTRA"];
+        let mut normalized = seq.clone();
+        normalized.normalize();
+        assert_eq!(normalized.header.track_count, 2);
+        let out = normalized.to_vec().expect("encoding succeeds");
+        assert_eq!(u16::from_le_bytes([out[10], out[11]]), 2);
+        assert_eq!(chunk_ids(&out), canonical);
+
+        let mut dropped = seq;
+        dropped.chunks.retain(|chunk| chunk.id() != *b"XTRA");
+        let out = dropped.to_vec().expect("encoding succeeds");
+        assert_eq!(u16::from_le_bytes([out[10], out[11]]), 2);
+        assert_eq!(chunk_ids(&out), canonical[..4]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn write_to_matches_to_vec() {
+        let seq = corpus().pop().unwrap();
+        let mut out = Vec::new();
+        seq.write_to(&mut out).expect("writing succeeds");
+        assert_eq!(out, seq.to_vec().unwrap());
+    }
+}
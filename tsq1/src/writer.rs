@@ -0,0 +1,313 @@
+//! Incremental TSQ1 encoding into an [`io::Write`] sink.
+//!
+//! [`Writer`] emits events as they arrive, which suits recording a live
+//! session where the full track is never held in memory.
+
+use alloc::vec::Vec;
+use std::io::{self, Seek, SeekFrom, Write};
+
+use crate::custom::CustomRegistry;
+use crate::event::Event;
+use crate::sequence::{Chunk, Header};
+use crate::write::{encode_chunk, push_chunk, write_event, write_file_header};
+use crate::Error;
+
+/// Streaming encoder for TSQ1 data.
+///
+/// ```no_run
+/// # use tsq1::{Event, EventKind, Header, MidiEvent};
+/// # fn main() -> Result<(), tsq1::Error> {
+/// let file = std::fs::File::create("take.tsq")?;
+/// let mut writer = tsq1::Writer::new_seekable(file, Header::new(480))?;
+/// writer.begin_track()?;
+/// writer.write_event(&Event::absolute(0, EventKind::Midi(MidiEvent::new(0x90, 60, 100))))?;
+/// writer.write_event(&Event::absolute(250_000, EventKind::Midi(MidiEvent::new(0x80, 60, 0))))?;
+/// writer.finish()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// A writer created with [`Writer::new_seekable`] writes each event
+/// straight through and back-patches the `"TRK "` chunk length when the
+/// track ends, and `TrackCount` in [`Writer::finish`]. One created with
+/// [`Writer::new`] buffers the open track instead, and since the header is
+/// already written, the tracks written must match `header.track_count`.
+///
+/// Chunks are written in the order given; nothing is reordered as
+/// [`Sequence::to_vec`](crate::Sequence::to_vec) does. Dropping the writer
+/// without calling [`Writer::finish`] leaves the open track's length and
+/// `TrackCount` unpatched.
+pub struct Writer<W> {
+    inner: W,
+    header: Header,
+    registry: CustomRegistry,
+    patch: Option<Patch<W>>,
+    /// Stream position of the file header.
+    start: u64,
+    /// Bytes written since `start`.
+    offset: u64,
+    tracks: u16,
+    track: Option<OpenTrack>,
+    scratch: Vec<u8>,
+    event: Vec<u8>,
+}
+
+/// Overwrites bytes at an absolute position, then returns to the end.
+type Patch<W> = fn(&mut W, u64, &[u8]) -> io::Result<()>;
+
+struct OpenTrack {
+    /// Offset of the chunk ID from `start`.
+    offset: u64,
+    len: u64,
+    /// Event bytes held back until the track ends, if not patching.
+    buf: Vec<u8>,
+}
+
+impl<W: Write> Writer<W> {
+    /// Write `header` to `inner`, buffering each track until it ends.
+    /// `header.track_count` is written as given.
+    pub fn new(inner: W, header: Header) -> Result<Self, Error> {
+        Writer::with_patch(inner, header, 0, None)
+    }
+}
+
+impl<W: Write + Seek> Writer<W> {
+    /// Write `header` at the current position of `inner`, streaming events
+    /// and patching lengths afterwards. `TrackCount` is written as 0 until
+    /// [`Writer::finish`].
+    pub fn new_seekable(mut inner: W, mut header: Header) -> Result<Self, Error> {
+        let start = inner.stream_position()?;
+        header.track_count = 0;
+        Writer::with_patch(
+            inner,
+            header,
+            start,
+            Some(|inner, position, bytes| {
+                let end = inner.stream_position()?;
+                inner.seek(SeekFrom::Start(position))?;
+                inner.write_all(bytes)?;
+                inner.seek(SeekFrom::Start(end)).map(|_| ())
+            }),
+        )
+    }
+}
+
+impl<W: Write> Writer<W> {
+    fn with_patch(
+        mut inner: W,
+        header: Header,
+        start: u64,
+        patch: Option<Patch<W>>,
+    ) -> Result<Self, Error> {
+        let mut bytes = Vec::new();
+        write_file_header(&mut bytes, &header, header.track_count);
+        inner.write_all(&bytes)?;
+        Ok(Writer {
+            inner,
+            offset: bytes.len() as u64,
+            header,
+            registry: CustomRegistry::new(),
+            patch,
+            start,
+            tracks: 0,
+            track: None,
+            scratch: Vec::new(),
+            event: Vec::new(),
+        })
+    }
+
+    /// Encode typed custom event values with the codecs in `registry`.
+    pub fn with_registry(mut self, registry: CustomRegistry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Number of `"TRK "` chunks begun so far.
+    pub fn track_count(&self) -> u16 {
+        self.tracks
+    }
+
+    /// End the open track, if any, and open a new `"TRK "` chunk.
+    pub fn begin_track(&mut self) -> Result<(), Error> {
+        self.end_track()?;
+        self.tracks = self
+            .tracks
+            .checked_add(1)
+            .ok_or(Error::DataOverflow("too many tracks"))?;
+        let offset = self.offset;
+        if self.patch.is_some() {
+            self.emit(&[b'T', b'R', b'K', b' ', 0, 0, 0, 0])?;
+        }
+        self.track = Some(OpenTrack {
+            offset,
+            len: 0,
+            buf: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// Append `event` to the open track.
+    pub fn write_event(&mut self, event: &Event) -> Result<(), Error> {
+        let three_bytes = self.header.midi_three_bytes();
+        let track = self
+            .track
+            .as_mut()
+            .ok_or(Error::Invalid("no track chunk is open"))?;
+        self.event.clear();
+        write_event(
+            event,
+            three_bytes,
+            &self.registry,
+            &mut self.scratch,
+            &mut self.event,
+        )?;
+        if track.len + self.event.len() as u64 > u32::MAX as u64 {
+            return Err(Error::DataOverflow("chunk too large"));
+        }
+        track.len += self.event.len() as u64;
+        if self.patch.is_none() {
+            track.buf.extend_from_slice(&self.event);
+            return Ok(());
+        }
+        let bytes = core::mem::take(&mut self.event);
+        let result = self.emit(&bytes);
+        self.event = bytes;
+        result
+    }
+
+    /// Close the open track, if any, writing or patching its length.
+    pub fn end_track(&mut self) -> Result<(), Error> {
+        let Some(track) = self.track.take() else {
+            return Ok(());
+        };
+        match self.patch {
+            Some(patch) => {
+                let position = self.start + track.offset + 4;
+                patch(&mut self.inner, position, &(track.len as u32).to_le_bytes())?;
+            }
+            None => {
+                let mut bytes = Vec::with_capacity(track.buf.len() + 8);
+                push_chunk(&mut bytes, b"TRK ", &track.buf)?;
+                self.emit(&bytes)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// End the open track, if any, and write `chunk` whole.
+    pub fn write_chunk(&mut self, chunk: &Chunk) -> Result<(), Error> {
+        self.end_track()?;
+        let mut data = Vec::new();
+        encode_chunk(chunk, &mut data)?;
+        let mut bytes = Vec::with_capacity(data.len() + 8);
+        push_chunk(&mut bytes, &chunk.id(), &data)?;
+        self.emit(&bytes)
+    }
+
+    /// End the open track, patch `TrackCount` and flush, returning the
+    /// underlying writer.
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.end_track()?;
+        match self.patch {
+            Some(patch) => {
+                patch(&mut self.inner, self.start + 10, &self.tracks.to_le_bytes())?;
+            }
+            None if self.tracks != self.header.track_count => {
+                return Err(Error::Invalid(
+                    "tracks written differ from TrackCount in the header",
+                ));
+            }
+            None => {}
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn emit(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.inner.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{EventKind, MetaEvent, MidiEvent};
+    use crate::sequence::{RawChunk, Sequence, Track};
+    use crate::tempo::{TempoEntry, TempoMap};
+    use alloc::vec;
+    use std::io::Cursor;
+
+    fn sample() -> Sequence {
+        let mut seq = Sequence::new(480);
+        seq.header.flags = crate::FLAG_MIDI_THREE_BYTES;
+        seq.tracks.push(Track::with_events(vec![
+            Event::musical(0, EventKind::Midi(MidiEvent::new(0xC1, 5, 0))),
+            Event::absolute(1_000, EventKind::SysEx(vec![0xF0, 0x7E, 0xF7])),
+            Event::musical(480, EventKind::Meta(MetaEvent::new(0x2F, vec![]))),
+        ]));
+        seq.tracks.push(Track::new());
+        seq.tracks.push(Track::with_events(vec![Event::musical(
+            960,
+            EventKind::Midi(MidiEvent::new(0x90, 60, 100)),
+        )]));
+        seq.chunks
+            .push(Chunk::TempoMap(TempoMap::from_entries(vec![TempoEntry {
+                tick: 0,
+                us_per_qn: 500_000,
+            }])));
+        seq.chunks.push(Chunk::Unknown(RawChunk {
+            id: *b"XTRA",
+            data: vec![0xAA; 5],
+        }));
+        seq
+    }
+
+    fn record<W: Write>(mut writer: Writer<W>, seq: &Sequence) -> Result<W, Error> {
+        for track in &seq.tracks {
+            writer.begin_track()?;
+            for event in &track.events {
+                writer.write_event(event)?;
+            }
+        }
+        for chunk in &seq.chunks {
+            writer.write_chunk(chunk)?;
+        }
+        writer.finish()
+    }
+
+    #[test]
+    fn matches_to_vec() {
+        let seq = sample();
+        let expected = seq.to_vec().unwrap();
+
+        let mut prefixed = Cursor::new(vec![0xEE; 3]);
+        prefixed.seek(SeekFrom::End(0)).unwrap();
+        let writer = Writer::new_seekable(prefixed, seq.header.clone()).unwrap();
+        let out = record(writer, &seq).unwrap().into_inner();
+        assert_eq!(out[..3], [0xEE; 3]);
+        assert_eq!(out[3..], expected);
+
+        let mut header = seq.header.clone();
+        header.track_count = 3;
+        let out = record(Writer::new(Vec::new(), header).unwrap(), &seq).unwrap();
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn rejects_misuse() {
+        let mut writer = Writer::new(Vec::new(), Header::new(480)).unwrap();
+        let event = Event::musical(0, EventKind::Midi(MidiEvent::new(0x90, 60, 100)));
+        assert!(matches!(writer.write_event(&event), Err(Error::Invalid(_))));
+        writer.begin_track().unwrap();
+        let bad = Event::musical(0, EventKind::Midi(MidiEvent::new(0x10, 60, 100)));
+        assert!(matches!(writer.write_event(&bad), Err(Error::Invalid(_))));
+        // The header promised no tracks.
+        assert!(matches!(writer.finish(), Err(Error::Invalid(_))));
+    }
+}